@@ -25,22 +25,22 @@ impl Map {
     }
 
     fn pipe_north(&self, position: Position) -> Option<Position> {
-        let allowed_north = vec!['|', 'F', '7'];
+        let allowed_north = vec!['|', 'F', '7', 'S'];
         self.pipe_allowed(position.north(), allowed_north)
     }
 
     fn pipe_south(&self, position: Position) -> Option<Position> {
-        let allowed_south = vec!['L', 'J', '|'];
+        let allowed_south = vec!['L', 'J', '|', 'S'];
         self.pipe_allowed(position.south(), allowed_south)
     }
 
     fn pipe_east(&self, position: Position) -> Option<Position> {
-        let allowed_east = vec!['-', '7', 'J'];
+        let allowed_east = vec!['-', '7', 'J', 'S'];
         self.pipe_allowed(position.east(), allowed_east)
     }
 
     fn pipe_west(&self, position: Position) -> Option<Position> {
-        let allowed_west = vec!['-', 'F', 'L'];
+        let allowed_west = vec!['-', 'F', 'L', 'S'];
         self.pipe_allowed(position.west(), allowed_west)
     }
 
@@ -134,12 +134,14 @@ impl Map {
     }
 
     // Returns false in case a loop is not found. The `visited` contains the positions that
-    // are part of the loop.
+    // are part of the loop, in the order in which they are connected. Positions on dead ends
+    // are removed when we go back from them.
     fn find_loop(&self, current: Position, parent: Position, visited: &mut Vec<Position>) -> bool {
         visited.push(current);
         let neighbors = self.neighbors(current);
         // if there are no neighbors it means that we reached a dead end.
         if neighbors.is_empty() {
+            visited.pop();
             return false;
         }
         for pos in neighbors {
@@ -153,9 +155,28 @@ impl Map {
                 return true;
             }
         }
+        visited.pop();
         false
     }
 
+    // Counts the tiles enclosed by the loop that goes through start. The shoelace formula
+    // gives the area of the polygon that joins the centres of the loop tiles, and by Pick's
+    // theorem that area is I + B / 2 - 1, where B is the number of tiles of the loop and I
+    // the number of tiles inside it.
+    fn count_enclosed(&self, start: Position) -> usize {
+        let mut visited: Vec<Position> = Vec::new();
+        if !self.find_loop(start, Position::default(), &mut visited) {
+            return 0;
+        }
+        let twice_area = visited
+            .iter()
+            .zip(visited.iter().cycle().skip(1))
+            .map(|(a, b)| a.column as isize * b.line as isize - b.column as isize * a.line as isize)
+            .sum::<isize>()
+            .abs();
+        ((twice_area - visited.len() as isize) / 2 + 1) as usize
+    }
+
     fn find(&self, value: char) -> Option<Position> {
         for (line, columns) in self.inner.iter().enumerate() {
             for (column, e) in columns.iter().enumerate() {
@@ -171,6 +192,11 @@ impl Map {
         let s_pos = self.find('S').unwrap();
         self.find_farthest_point(s_pos)
     }
+
+    fn part_2(&self) -> usize {
+        let s_pos = self.find('S').unwrap();
+        self.count_enclosed(s_pos)
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Copy, Clone, Ord, PartialOrd, Eq)]
@@ -206,6 +232,7 @@ fn main() {
     let input = read_lines(path);
     let map = Map::from(input);
     println!("Part 1: {}", map.part_1());
+    println!("Part 2: {}", map.part_2());
 }
 
 #[cfg(test)]
@@ -232,4 +259,101 @@ LJ.."#
         assert_eq!(neighbors, expected_neighbors);
         assert_eq!(map.part_1(), 8);
     }
+
+    #[test]
+    fn test_part_2() {
+        let input: Vec<String> = r#"...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|..|.|..|.
+.L--J.L--J.
+..........."#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.part_2(), 4);
+
+        // The loop can be squeezed between pipes without enclosing the tiles in between.
+        let input: Vec<String> = r#"..........
+.S------7.
+.|F----7|.
+.||....||.
+.||....||.
+.|L-7F-J|.
+.|..||..|.
+.L--JL--J.
+.........."#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.part_2(), 4);
+
+        let input: Vec<String> = r#".F----7F7F7F7F-7....
+.|F--7||||||||FJ....
+.||.FJ||||||||L7....
+FJL7L7LJLJ||LJ.L-7..
+L--J.L7...LJS7F-7L7.
+....F-J..F7FJ|L7L7L7
+....L7.F7||L7|.L7L7|
+.....|FJLJ|FJ|F7|.LJ
+....FJL-7.||.||||...
+....L---J.LJ.LJLJ..."#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.part_2(), 8);
+
+        // Pipes that are not part of the loop count as tiles too.
+        let input: Vec<String> = r#"...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|F7|.|LJ|.
+.L--J.L--J.
+..........."#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.part_2(), 4);
+
+        // The loop of part 1 is surrounded by pipes that are not connected to it. The `L` in
+        // the middle is the only tile inside.
+        let input: Vec<String> = r#"7-F7-
+.FJ|7
+SJLL7
+|F--J
+LJ.LJ"#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.part_1(), 8);
+        assert_eq!(map.part_2(), 1);
+    }
+
+    #[test]
+    fn test_no_loop() {
+        // Both ways out of `S` end at a ground tile.
+        let input: Vec<String> = r#".....
+.S-7.
+.|.|.
+.L-.|
+....."#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        let s_pos = map.find('S').unwrap();
+        assert_eq!(map.count_enclosed(s_pos), 0);
+        assert_eq!(map.part_1(), 0);
+    }
 }
@@ -1,3 +1,5 @@
+use std::env;
+use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use utils::read_lines;
 
@@ -95,6 +97,137 @@ impl Cards {
         }
         total
     }
+
+    // Returns the indexes of the cards that win a copy of the card at `index`.
+    fn winners_of(&self, index: usize) -> Vec<usize> {
+        (0..index)
+            .filter(|&i| self.inner[i].scratch_cards().contains(&index))
+            .collect()
+    }
+
+    // Returns the total number of instances (original + copies) of each card.
+    fn instances(&self) -> Vec<usize> {
+        let mut instances = vec![1; self.inner.len()];
+        for (index, card) in self.inner.iter().enumerate() {
+            for c in card.scratch_cards() {
+                if c < instances.len() {
+                    instances[c] += instances[index];
+                }
+            }
+        }
+        instances
+    }
+
+    // Traces back all the copies of the card with `number` to the original cards that
+    // caused them. Returns None if there is no such card.
+    fn explain(&self, number: usize) -> Option<CopyTrace> {
+        if !(1..=self.inner.len()).contains(&number) {
+            return None;
+        }
+        let target = number - 1;
+        let instances = self.instances();
+
+        // Walk the wins backwards one level at a time. `paths[i]` is the number of chains of
+        // the current length that start at the card at index `i` and end up at `target`.
+        let mut levels = Vec::new();
+        let mut paths = vec![0; self.inner.len()];
+        paths[target] = 1;
+        loop {
+            let mut next = vec![0; self.inner.len()];
+            for (index, count) in paths.iter().enumerate().filter(|(_, c)| **c > 0) {
+                for winner in self.winners_of(index) {
+                    next[winner] += count;
+                }
+            }
+            let origins = next
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c > 0)
+                .map(|(index, c)| (index + 1, *c))
+                .collect::<Vec<(usize, usize)>>();
+            if origins.is_empty() {
+                break;
+            }
+            levels.push(origins);
+            paths = next;
+        }
+
+        // The cards contributing to `target` are all the cards from which there is a chain of
+        // wins to it, which is exactly what the levels contain.
+        let mut contributing = levels
+            .iter()
+            .flatten()
+            .map(|(origin, _)| origin - 1)
+            .collect::<Vec<usize>>();
+        contributing.push(target);
+        contributing.sort();
+        contributing.dedup();
+
+        let mut edges = Vec::new();
+        for &to in contributing.iter() {
+            for from in self.winners_of(to) {
+                edges.push((from + 1, to + 1, instances[from]));
+            }
+        }
+
+        Some(CopyTrace {
+            card: number,
+            instances: instances[target],
+            levels,
+            edges,
+        })
+    }
+}
+
+// Explains where the instances of a card come from. Each copy of `card` can be traced back
+// to an original card through a chain of wins, and the chain length is the level of the copy.
+#[derive(Debug, Clone, PartialEq)]
+struct CopyTrace {
+    card: usize,
+    instances: usize,
+    // `levels[d]` holds `(origin card, copies)` pairs for the copies that are `d + 1` wins
+    // away from their original card.
+    levels: Vec<Vec<(usize, usize)>>,
+    // The edges of the DAG of cards that contribute to `card`, as `(from, to, copies)`. The
+    // number of copies is the number of instances of `from`, since each of them wins one `to`.
+    edges: Vec<(usize, usize, usize)>,
+}
+
+impl CopyTrace {
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cards {\n");
+        dot.push_str(&format!(
+            "    \"Card {}\" [shape=doublecircle, label=\"Card {}\\n{} instances\"];\n",
+            self.card, self.card, self.instances
+        ));
+        for (from, to, copies) in self.edges.iter() {
+            dot.push_str(&format!(
+                "    \"Card {from}\" -> \"Card {to}\" [label=\"{copies}\"];\n"
+            ));
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+impl Display for CopyTrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Card {}: {} instances (1 original, {} copies)",
+            self.card,
+            self.instances,
+            self.instances - 1
+        )?;
+        for (depth, origins) in self.levels.iter().enumerate() {
+            let copies = origins.iter().map(|(_, c)| c).sum::<usize>();
+            writeln!(f, "  level {}: {} copies", depth + 1, copies)?;
+            for (origin, copies) in origins.iter() {
+                writeln!(f, "    from card {origin}: {copies}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl From<Vec<String>> for Cards {
@@ -111,12 +244,41 @@ fn main() {
     let cards = Cards::from(input);
     println!("Part 1: {}", cards.score_part1());
     println!("Part 2: {}", cards.score_part2()); // 8477787
+
+    // Pass a card number to explain where its copies come from, and `--dot` to get the
+    // explanation as a Graphviz graph.
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let Some(arg) = args.first() else {
+        return;
+    };
+    let Ok(number) = arg.parse::<usize>() else {
+        eprintln!("Usage: day4 [--dot] [<card number>]");
+        return;
+    };
+    match cards.explain(number) {
+        Some(trace) if flags.iter().any(|flag| flag == "--dot") => println!("{}", trace.to_dot()),
+        Some(trace) => print!("{trace}"),
+        None => eprintln!("There is no card {number}"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{Card, Cards};
 
+    fn test_input() -> Vec<String> {
+        r#"Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11"#
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>()
+    }
+
     #[test]
     fn test_parse_line() {
         let input = "Card   7: 89 70 36 38 86 50 94 62 56  3 |  7  8 56 14 58 65 63 36 54 59 78 79 11  2 69 55 61 39 19 60  4 99 90 17 95".to_string();
@@ -165,4 +327,64 @@ Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11"#
         let cards = Cards::from(input.lines().map(String::from).collect::<Vec<String>>());
         assert_eq!(cards.score_part2(), 30);
     }
+
+    #[test]
+    fn test_instances() {
+        let cards = Cards::from(test_input());
+        assert_eq!(cards.instances(), vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(cards.instances().iter().sum::<usize>(), cards.score_part2());
+    }
+
+    #[test]
+    fn test_explain() {
+        let cards = Cards::from(test_input());
+        let trace = cards.explain(4).unwrap();
+        assert_eq!(trace.instances, 8);
+        assert_eq!(
+            trace.levels,
+            vec![
+                vec![(1, 1), (2, 1), (3, 1)],
+                vec![(1, 2), (2, 1)],
+                vec![(1, 1)]
+            ]
+        );
+        assert_eq!(
+            trace.edges,
+            vec![
+                (1, 2, 1),
+                (1, 3, 1),
+                (2, 3, 2),
+                (1, 4, 1),
+                (2, 4, 2),
+                (3, 4, 4)
+            ]
+        );
+        assert_eq!(
+            trace.to_string(),
+            r#"Card 4: 8 instances (1 original, 7 copies)
+  level 1: 3 copies
+    from card 1: 1
+    from card 2: 1
+    from card 3: 1
+  level 2: 3 copies
+    from card 1: 2
+    from card 2: 1
+  level 3: 1 copies
+    from card 1: 1
+"#
+        );
+
+        // The first card is never won, so it only has the original instance.
+        let trace = cards.explain(1).unwrap();
+        assert_eq!(trace.instances, 1);
+        assert!(trace.levels.is_empty());
+        assert_eq!(
+            trace.to_dot(),
+            "digraph cards {\n    \"Card 1\" [shape=doublecircle, label=\"Card 1\\n1 instances\"];\n}"
+        );
+
+        // The cards are numbered from 1.
+        assert_eq!(cards.explain(0), None);
+        assert_eq!(cards.explain(7), None);
+    }
 }
@@ -1,16 +1,23 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 use std::path::PathBuf;
 use utils::read_lines;
 
+// The numbers in the almanac fit in a `u64`, but we do all the math on `u128`. This way
+// `source + length` and the end of the intervals can't overflow, even for ranges that go
+// all the way to `u64::MAX`.
+type Value = u128;
+
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 struct SeedRange {
-    source: usize,
-    destination: usize,
-    length: usize,
+    source: Value,
+    destination: Value,
+    length: Value,
 }
 
 impl SeedRange {
-    #[cfg(test)]
-    fn new(source: usize, destination: usize, length: usize) -> Self {
+    fn new(source: Value, destination: Value, length: Value) -> Self {
         Self {
             source,
             destination,
@@ -19,33 +26,72 @@ impl SeedRange {
     }
 
     // Maps `source` to a destination, returns `None` if `source` is not in range.
-    fn find_destination_for(&self, source: usize) -> Option<usize> {
+    fn find_destination_for(&self, source: Value) -> Option<Value> {
         if source >= self.source && source < self.source + self.length {
             let offset = source - self.source;
             return Some(self.destination + offset);
         }
         None
     }
+
+    // The interval of sources covered by this range.
+    fn sources(&self) -> Range<Value> {
+        self.source..self.source + self.length
+    }
+
+    // The interval of destinations covered by this range.
+    fn destinations(&self) -> Range<Value> {
+        self.destination..self.destination + self.length
+    }
+
+    // Returns the same range, but going from destination to source.
+    fn invert(&self) -> SeedRange {
+        SeedRange::new(self.destination, self.source, self.length)
+    }
 }
 
-impl From<&str> for SeedRange {
-    fn from(value: &str) -> Self {
-        let tokens = value
-            .split(' ')
-            .map(|v| v.parse::<usize>().unwrap())
-            .collect::<Vec<usize>>();
-        Self {
+impl TryFrom<&str> for SeedRange {
+    type Error = ParseErrorKind;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let tokens = parse_numbers(value)?;
+        if tokens.len() != 3 {
+            return Err(ParseErrorKind::InvalidRange(tokens.len()));
+        }
+        Ok(Self {
             source: tokens[1],
             destination: tokens[0],
             length: tokens[2],
+        })
+    }
+}
+
+// Merges the intervals that overlap or touch, returning them sorted.
+fn merge_intervals(mut intervals: Vec<Range<Value>>) -> Vec<Range<Value>> {
+    intervals.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<Value>> = Vec::new();
+    for interval in intervals.into_iter().filter(|r| !r.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
         }
     }
+    merged
+}
+
+// Two ranges of the same map that cover some of the same sources.
+#[derive(Debug, Clone, PartialEq)]
+enum RangeConflict {
+    // The ranges map the shared sources to the same destinations, so one of them is redundant.
+    Overlapping(SeedRange, SeedRange),
+    // The ranges map the shared sources to different destinations.
+    Contradictory(SeedRange, SeedRange),
 }
 
-#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 enum Category {
     #[default]
-    Seed = 0x0,
+    Seed,
     Soil,
     Fertilizer,
     Water,
@@ -53,6 +99,8 @@ enum Category {
     Temperature,
     Humidity,
     Location,
+    // Any other category that an almanac might define.
+    Custom(String),
 }
 
 impl TryFrom<&str> for Category {
@@ -68,11 +116,85 @@ impl TryFrom<&str> for Category {
             "temperature" => Ok(Category::Temperature),
             "humidity" => Ok(Category::Humidity),
             "location" => Ok(Category::Location),
+            // The name of a category is a single word, as it is part of "<source>-to-<destination>".
+            _ if !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                Ok(Category::Custom(value.to_string()))
+            }
             _ => Err(()),
         }
     }
 }
 
+impl Display for Category {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Category::Seed => "seed",
+            Category::Soil => "soil",
+            Category::Fertilizer => "fertilizer",
+            Category::Water => "water",
+            Category::Light => "light",
+            Category::Temperature => "temperature",
+            Category::Humidity => "humidity",
+            Category::Location => "location",
+            Category::Custom(name) => name,
+        };
+        write!(f, "{name}")
+    }
+}
+
+// Errors returned when looking for the maps that lead from one category to another.
+#[derive(Debug, Clone, PartialEq)]
+enum RouteError {
+    // No map starts or ends at this category.
+    UnknownCategory(Category),
+    // Both categories exist, but there is no chain of maps between them.
+    Disconnected(Category, Category),
+}
+
+impl Display for RouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteError::UnknownCategory(category) => {
+                write!(f, "the almanac has no map for category `{category}`")
+            }
+            RouteError::Disconnected(from, to) => {
+                write!(f, "there is no way to get from `{from}` to `{to}`")
+            }
+        }
+    }
+}
+
+// Errors returned when looking for the lowest location of the seed ranges in part 2.
+#[derive(Debug, Clone, PartialEq)]
+enum SeedRangeError {
+    // The seeds are not pairs of (start, length), the last one has no length.
+    UnpairedSeed(Value),
+    // The seeds are pairs, but all of them have a length of 0.
+    EmptyRanges,
+    Route(RouteError),
+}
+
+impl From<RouteError> for SeedRangeError {
+    fn from(value: RouteError) -> Self {
+        SeedRangeError::Route(value)
+    }
+}
+
+impl Display for SeedRangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeedRangeError::UnpairedSeed(seed) => {
+                write!(
+                    f,
+                    "the seeds are not pairs of (start, length), {seed} has no length"
+                )
+            }
+            SeedRangeError::EmptyRanges => write!(f, "all the seed ranges are empty"),
+            SeedRangeError::Route(e) => write!(f, "{e}"),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq)]
 struct SeedMap {
     source_category: Category,
@@ -94,98 +216,472 @@ impl SeedMap {
     }
 
     // Returns the destination of the passed `source`.
-    fn find_destination_for(&self, source: usize) -> usize {
+    fn find_destination_for(&self, source: Value) -> Value {
         self.map
             .iter()
             .find_map(|r| r.find_destination_for(source))
             .unwrap_or(source)
     }
+
+    // Maps a whole interval of sources at once. The interval is split at the boundaries of
+    // the `SeedRange`s it overlaps, and the parts that are not covered by any range are
+    // passed through unchanged, just like `find_destination_for` does for single values.
+    fn find_destinations_for_range(&self, sources: Range<Value>) -> Vec<Range<Value>> {
+        if sources.is_empty() {
+            return Vec::new();
+        }
+        let mut ranges = self.map.clone();
+        ranges.sort_by_key(|r| r.source);
+
+        let mut destinations = Vec::new();
+        let mut cursor = sources.start;
+        for range in ranges.iter() {
+            let covered = range.sources();
+            if covered.end <= cursor {
+                continue;
+            }
+            if covered.start >= sources.end {
+                break;
+            }
+            if cursor < covered.start {
+                // The gap before this range is not mapped.
+                destinations.push(cursor..covered.start);
+                cursor = covered.start;
+            }
+            let end = Value::min(sources.end, covered.end);
+            let offset = cursor - covered.start;
+            destinations
+                .push(range.destination + offset..range.destination + offset + end - cursor);
+            cursor = end;
+        }
+        if cursor < sources.end {
+            destinations.push(cursor..sources.end);
+        }
+        destinations
+    }
+
+    // Same as `find_destinations_for_range`, but for a list of source intervals.
+    fn find_destinations_for_ranges(&self, sources: &[Range<Value>]) -> Vec<Range<Value>> {
+        sources
+            .iter()
+            .flat_map(|r| self.find_destinations_for_range(r.clone()))
+            .collect()
+    }
+
+    // Returns all the pairs of ranges that cover the same sources.
+    fn conflicts(&self) -> Vec<RangeConflict> {
+        let mut conflicts = Vec::new();
+        for (i, a) in self.map.iter().enumerate() {
+            for b in self.map[i + 1..].iter() {
+                let (first, second) = (a.sources(), b.sources());
+                if first.start >= second.end || second.start >= first.end {
+                    continue;
+                }
+                // Overlapping ranges agree only if they shift the sources by the same amount.
+                if a.destination.wrapping_sub(a.source) == b.destination.wrapping_sub(b.source) {
+                    conflicts.push(RangeConflict::Overlapping(*a, *b));
+                } else {
+                    conflicts.push(RangeConflict::Contradictory(*a, *b));
+                }
+            }
+        }
+        conflicts
+    }
+
+    // Returns the ranges of the map, sorted by source, together with the identity ranges
+    // for the gaps between them, such that all the values up to `Value::MAX` are covered.
+    fn pieces(&self) -> Vec<SeedRange> {
+        let mut ranges = self.map.clone();
+        ranges.sort_by_key(|r| r.source);
+
+        let mut pieces = Vec::new();
+        let mut cursor = 0;
+        for range in ranges {
+            if cursor < range.source {
+                pieces.push(SeedRange::new(cursor, cursor, range.source - cursor));
+            }
+            pieces.push(range);
+            cursor = range.source + range.length;
+        }
+        if cursor < Value::MAX {
+            pieces.push(SeedRange::new(cursor, cursor, Value::MAX - cursor));
+        }
+        pieces
+    }
+
+    // Builds a single map that is equivalent to applying `self` and then `next`.
+    // The ranges of the resulting map are sorted by source, and the values that end up
+    // unchanged are left out as they are implicitly mapped to themselves.
+    fn compose(&self, next: &SeedMap) -> SeedMap {
+        let mut composed = SeedMap::new(
+            self.source_category.clone(),
+            next.destination_category.clone(),
+        );
+        for piece in self.pieces() {
+            // `find_destinations_for_range` returns the parts of the interval in order, so
+            // we can find the source of each of them by adding up their lengths.
+            let mut source = piece.source;
+            for destinations in next.find_destinations_for_range(piece.destinations()) {
+                let length = destinations.end - destinations.start;
+                if destinations.start != source {
+                    match composed.map.last_mut() {
+                        Some(last)
+                            if last.source + last.length == source
+                                && last.destination + last.length == destinations.start =>
+                        {
+                            last.length += length;
+                        }
+                        _ => {
+                            composed.push_range(SeedRange::new(source, destinations.start, length))
+                        }
+                    }
+                }
+                source += length;
+            }
+        }
+        composed
+    }
+
+    // Returns the map going from destination to source. This is only possible when
+    // every destination comes from exactly one source, so it returns `None` otherwise.
+    fn invert(&self) -> Option<SeedMap> {
+        let inverted = SeedMap {
+            source_category: self.destination_category.clone(),
+            destination_category: self.source_category.clone(),
+            map: self.map.iter().map(SeedRange::invert).collect(),
+        };
+        // The values outside the ranges are mapped to themselves, so the ranges must take
+        // and give back exactly the same values.
+        let sources = merge_intervals(self.map.iter().map(SeedRange::sources).collect());
+        let destinations = merge_intervals(self.map.iter().map(SeedRange::destinations).collect());
+        if !self.conflicts().is_empty()
+            || !inverted.conflicts().is_empty()
+            || sources != destinations
+        {
+            return None;
+        }
+        Some(inverted)
+    }
 }
 
 #[derive(Debug)]
 struct Almanac {
-    seeds: Vec<usize>,
+    seeds: Vec<Value>,
+    // The maps in the order in which they appear in the almanac.
     maps: Vec<SeedMap>,
+    // The maps as a graph: for each source category we keep the indexes in `maps` of the
+    // maps starting at that category.
+    graph: HashMap<Category, Vec<usize>>,
+}
+
+// What went wrong while parsing an almanac.
+#[derive(Debug, Clone, PartialEq)]
+enum ParseErrorKind {
+    // There is no `seeds:` line.
+    MissingSeeds,
+    // There is more than one `seeds:` line.
+    DuplicateSeeds,
+    InvalidNumber(String),
+    // A map header is not of the form `<source>-to-<destination> map:`.
+    InvalidHeader(String),
+    // A range must have exactly 3 numbers, this is how many we found instead.
+    InvalidRange(usize),
+    // Numbers that are neither seeds nor part of a map.
+    UnexpectedLine,
+}
+
+type ParseError = utils::ParseError<ParseErrorKind>;
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::MissingSeeds => write!(f, "the almanac has no seeds"),
+            ParseErrorKind::DuplicateSeeds => write!(f, "the seeds are already defined"),
+            ParseErrorKind::InvalidNumber(token) => write!(f, "`{token}` is not a valid number"),
+            ParseErrorKind::InvalidHeader(header) => write!(
+                f,
+                "`{header}` is not of the form `<source>-to-<destination> map:`"
+            ),
+            ParseErrorKind::InvalidRange(count) => {
+                write!(f, "expected 3 numbers for a range, found {count}")
+            }
+            ParseErrorKind::UnexpectedLine => write!(f, "numbers outside of seeds or a map"),
+        }
+    }
+}
+
+// Parses numbers separated by any amount of whitespace.
+fn parse_numbers(input: &str) -> Result<Vec<Value>, ParseErrorKind> {
+    input
+        .split_whitespace()
+        .map(|token| {
+            token
+                .parse::<u64>()
+                .map(Value::from)
+                .map_err(|_| ParseErrorKind::InvalidNumber(token.to_string()))
+        })
+        .collect()
+}
+
+// Parses the `<source>-to-<destination> map:` header of a map.
+fn parse_header(line: &str) -> Result<SeedMap, ParseErrorKind> {
+    let invalid_header = || ParseErrorKind::InvalidHeader(line.to_string());
+    let mapping = line
+        .strip_suffix("map:")
+        .ok_or_else(invalid_header)?
+        .trim_end();
+    let (source, destination) = mapping.split_once("-to-").ok_or_else(invalid_header)?;
+    Ok(SeedMap::new(
+        Category::try_from(source).map_err(|_| invalid_header())?,
+        Category::try_from(destination).map_err(|_| invalid_header())?,
+    ))
+}
+
+// The section of the almanac that we're currently parsing.
+enum Section {
+    Start,
+    Seeds,
+    Map(SeedMap),
 }
 
 impl From<Vec<String>> for Almanac {
+    // Panics if the almanac is not valid, `Almanac::parse` should be used to handle errors.
     fn from(lines: Vec<String>) -> Self {
-        const SEEDS_START_LINE: &str = "seeds: ";
-        let seeds = lines[0][SEEDS_START_LINE.len()..]
-            .split(' ')
-            .map(|s| s.parse::<usize>().unwrap())
-            .collect::<Vec<usize>>();
+        Self::parse(&lines).unwrap()
+    }
+}
 
+impl Almanac {
+    // Parses the almanac section by section. A section starts either with `seeds:` or with a
+    // map header, and keeps going until the next section starts, so blank lines are only
+    // cosmetic. The seeds can be spread across several lines.
+    fn parse(lines: &[String]) -> Result<Self, ParseError> {
+        const SEEDS_START: &str = "seeds:";
+        let mut seeds = None;
         let mut maps = Vec::new();
-        let mut seed_map = SeedMap::default();
-        // the second line is empty, we just skip it.
-        for line in lines[2..].iter() {
-            if line.contains("map") {
-                // we're starting a new SeedMap.
-                let (mapping, _) = line.split_once(' ').unwrap();
-                let (source, destination) = mapping.split_once("-to-").unwrap();
-                seed_map = SeedMap::new(
-                    Category::try_from(source).unwrap(),
-                    Category::try_from(destination).unwrap(),
-                );
-            } else if line.is_empty() {
-                // The configuration of one seed map is done. We need to push it to `maps`.
-                maps.push(seed_map.clone());
+        let mut section = Section::Start;
+
+        for (index, line) in lines.iter().enumerate() {
+            // `trim` also takes care of the `\r` in case the lines end with CRLF.
+            let line = line.trim();
+            let error = |kind| ParseError {
+                line: index + 1,
+                kind,
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(numbers) = line.strip_prefix(SEEDS_START) {
+                if seeds.is_some() {
+                    return Err(error(ParseErrorKind::DuplicateSeeds));
+                }
+                seeds = Some(parse_numbers(numbers).map_err(error)?);
+                if let Section::Map(seed_map) = std::mem::replace(&mut section, Section::Seeds) {
+                    maps.push(seed_map);
+                }
+            } else if line.ends_with(':') {
+                let seed_map = parse_header(line).map_err(error)?;
+                if let Section::Map(seed_map) =
+                    std::mem::replace(&mut section, Section::Map(seed_map))
+                {
+                    maps.push(seed_map);
+                }
             } else {
-                seed_map.push_range(SeedRange::from(line.as_str()));
+                match &mut section {
+                    Section::Start => return Err(error(ParseErrorKind::UnexpectedLine)),
+                    Section::Seeds => seeds
+                        .get_or_insert_with(Vec::new)
+                        .extend(parse_numbers(line).map_err(error)?),
+                    Section::Map(seed_map) => {
+                        seed_map.push_range(SeedRange::try_from(line).map_err(error)?)
+                    }
+                }
             }
         }
-        // We need to push the last created seedsmap.
-        maps.push(seed_map);
+        // We need to push the last map.
+        if let Section::Map(seed_map) = section {
+            maps.push(seed_map);
+        }
 
-        Self { seeds, maps }
+        let seeds = seeds.ok_or(ParseError {
+            line: 1,
+            kind: ParseErrorKind::MissingSeeds,
+        })?;
+        Ok(Self::new(seeds, maps))
     }
-}
 
-impl Almanac {
-    fn find_location_for_seed(&self, seed: &usize) -> usize {
+    fn new(seeds: Vec<Value>, maps: Vec<SeedMap>) -> Self {
+        let mut graph: HashMap<Category, Vec<usize>> = HashMap::new();
+        for (index, seed_map) in maps.iter().enumerate() {
+            graph
+                .entry(seed_map.source_category.clone())
+                .or_default()
+                .push(index);
+        }
+        Self { seeds, maps, graph }
+    }
+
+    fn has_category(&self, category: &Category) -> bool {
         self.maps
             .iter()
-            .fold(*seed, |res, seed_map| seed_map.find_destination_for(res))
+            .any(|m| m.source_category == *category || m.destination_category == *category)
     }
 
-    fn find_lowest_location(&self) -> usize {
-        self.seeds
+    // Returns the shortest chain of maps that goes from `from` to `to`. The maps can appear
+    // in any order in the almanac, we only look at their categories.
+    fn route(&self, from: &Category, to: &Category) -> Result<Vec<&SeedMap>, RouteError> {
+        for category in [from, to] {
+            if !self.has_category(category) {
+                return Err(RouteError::UnknownCategory(category.clone()));
+            }
+        }
+
+        // Breadth first search, keeping for each category the map through which we reached it.
+        let mut reached_through: HashMap<&Category, Option<usize>> = HashMap::new();
+        reached_through.insert(from, None);
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                break;
+            }
+            for &index in self.graph.get(category).into_iter().flatten() {
+                let next = &self.maps[index].destination_category;
+                if !reached_through.contains_key(next) {
+                    reached_through.insert(next, Some(index));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut route = Vec::new();
+        let mut category = to;
+        loop {
+            match reached_through.get(category) {
+                None => return Err(RouteError::Disconnected(from.clone(), to.clone())),
+                Some(None) => break,
+                Some(Some(index)) => {
+                    route.push(&self.maps[*index]);
+                    category = &self.maps[*index].source_category;
+                }
+            }
+        }
+        route.reverse();
+        Ok(route)
+    }
+
+    // Maps `value` from the `from` category to the `to` category.
+    fn map(&self, value: Value, from: &Category, to: &Category) -> Result<Value, RouteError> {
+        Ok(self
+            .route(from, to)?
+            .iter()
+            .fold(value, |res, seed_map| seed_map.find_destination_for(res)))
+    }
+
+    fn find_location_for_seed(&self, seed: &Value) -> Result<Value, RouteError> {
+        self.map(*seed, &Category::Seed, &Category::Location)
+    }
+
+    // Returns `None` if there are no seeds.
+    fn find_lowest_location(&self) -> Result<Option<Value>, RouteError> {
+        let locations = self
+            .seeds
             .iter()
             .map(|seed| self.find_location_for_seed(seed))
+            .collect::<Result<Vec<Value>, RouteError>>()?;
+        Ok(locations.into_iter().min())
+    }
+
+    // In part 2 the seeds are pairs of (start, length). Fails if there is a seed left
+    // without a length.
+    fn seed_ranges(&self) -> Result<Vec<Range<Value>>, SeedRangeError> {
+        let pairs = self.seeds.chunks_exact(2);
+        if let [seed] = pairs.remainder() {
+            return Err(SeedRangeError::UnpairedSeed(*seed));
+        }
+        Ok(pairs.map(|pair| pair[0]..pair[0] + pair[1]).collect())
+    }
+
+    // Maps the seed intervals through all the maps, returning the location intervals.
+    fn find_location_ranges_for(
+        &self,
+        seeds: &[Range<Value>],
+    ) -> Result<Vec<Range<Value>>, RouteError> {
+        Ok(self
+            .route(&Category::Seed, &Category::Location)?
+            .iter()
+            .fold(seeds.to_vec(), |res, seed_map| {
+                seed_map.find_destinations_for_ranges(&res)
+            }))
+    }
+
+    // Composes all the maps into a single one going from seed to location.
+    fn compose(&self) -> Result<SeedMap, RouteError> {
+        let identity = SeedMap::new(Category::Seed, Category::Seed);
+        Ok(self
+            .route(&Category::Seed, &Category::Location)?
+            .iter()
+            .fold(identity, |composed, seed_map| composed.compose(seed_map)))
+    }
+
+    // Returns the seed that ends up at `location`, or `None` if the almanac can't be
+    // inverted because some locations are reached from more than one seed.
+    fn find_seed_for_location(&self, location: Value) -> Result<Option<Value>, RouteError> {
+        Ok(self
+            .compose()?
+            .invert()
+            .map(|inverted| inverted.find_destination_for(location)))
+    }
+
+    // Fails if the seeds are not valid pairs of (start, length), if all of the intervals
+    // are empty, or if there is no way from the seeds to the locations.
+    fn find_lowest_location_with_seed_range(&self) -> Result<Value, SeedRangeError> {
+        // Instead of going through every seed, we map whole intervals of seeds. The lowest
+        // location is the lowest start of the resulting location intervals.
+        self.find_location_ranges_for(&self.seed_ranges()?)?
+            .iter()
+            .map(|r| r.start)
             .min()
-            .unwrap()
-    }
-
-    fn find_lowest_location_with_seed_range(&self) -> usize {
-        // this has to go through 1680883088 numbers, so it's rather slow.
-        self.seeds
-            .chunks(2)
-            .map(|window| {
-                let start = window[0];
-                let end = start + window[1];
-                (start..end)
-                    .map(|seed| self.find_location_for_seed(&seed))
-                    .min()
-                    .unwrap()
-            })
-            .min()
-            .unwrap()
+            .ok_or(SeedRangeError::EmptyRanges)
     }
 }
 
 fn main() {
     let path = PathBuf::from("src/day5/day5.txt");
     let input = read_lines(path);
-    let almanac = Almanac::from(input);
-    println!("Part 1: {}", almanac.find_lowest_location());
-    println!("Part 2: {}", almanac.find_lowest_location_with_seed_range()); // 99751240
+    let almanac = match Almanac::parse(&input) {
+        Ok(almanac) => almanac,
+        Err(e) => {
+            eprintln!("Invalid almanac: {e}");
+            return;
+        }
+    };
+    match almanac.find_lowest_location() {
+        Ok(Some(lowest_location)) => println!("Part 1: {lowest_location}"),
+        Ok(None) => eprintln!("Part 1: there are no seeds"),
+        Err(e) => {
+            eprintln!("Invalid almanac: {e}");
+            return;
+        }
+    }
+    match almanac.find_lowest_location_with_seed_range() {
+        Ok(lowest_location) => {
+            println!("Part 2: {}", lowest_location); // 99751240
+            if let Ok(Some(seed)) = almanac.find_seed_for_location(lowest_location) {
+                println!("The lowest location comes from seed {seed}");
+            }
+        }
+        Err(e) => eprintln!("Part 2: {e}"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::SeedRange;
-    use crate::{Almanac, Category, SeedMap};
+    use crate::{
+        Almanac, Category, ParseError, ParseErrorKind, RangeConflict, RouteError, SeedMap,
+        SeedRangeError, Value,
+    };
 
     fn test_input() -> Vec<String> {
         r#"seeds: 79 14 55 13
@@ -276,7 +772,7 @@ humidity-to-location map:
         let input = test_input();
         let almanac = Almanac::from(input);
 
-        assert_eq!(almanac.find_lowest_location(), 35);
+        assert_eq!(almanac.find_lowest_location(), Ok(Some(35)));
     }
 
     #[test]
@@ -284,6 +780,367 @@ humidity-to-location map:
         let input = test_input();
         let almanac = Almanac::from(input);
 
-        assert_eq!(almanac.find_lowest_location_with_seed_range(), 46);
+        assert_eq!(almanac.find_lowest_location_with_seed_range(), Ok(46));
+    }
+
+    #[test]
+    fn test_map_interval() {
+        let seed_map = SeedMap {
+            source_category: Category::Soil,
+            destination_category: Category::Seed,
+            map: vec![SeedRange::new(20, 30, 5), SeedRange::new(0, 5, 10)],
+        };
+
+        // Fully inside a range.
+        assert_eq!(seed_map.find_destinations_for_range(2..4), vec![7..9]);
+        // Fully in a gap.
+        assert_eq!(seed_map.find_destinations_for_range(12..18), vec![12..18]);
+        // Split over both ranges and the gaps around them.
+        assert_eq!(
+            seed_map.find_destinations_for_range(5..40),
+            vec![10..15, 10..20, 30..35, 25..40]
+        );
+        assert_eq!(
+            seed_map.find_destinations_for_ranges(&[0..1, 24..26]),
+            vec![5..6, 34..35, 25..26]
+        );
+    }
+
+    #[test]
+    fn test_location_ranges() {
+        let almanac = Almanac::from(test_input());
+
+        assert_eq!(almanac.seed_ranges(), Ok(vec![79..93, 55..68]));
+        // Every seed in the intervals must end up in one of the location intervals.
+        let locations = almanac
+            .find_location_ranges_for(&almanac.seed_ranges().unwrap())
+            .unwrap();
+        for seed in (79..93).chain(55..68) {
+            let location = almanac.find_location_for_seed(&seed).unwrap();
+            assert!(locations.iter().any(|r| r.contains(&location)));
+        }
+        assert_eq!(
+            locations.iter().map(|r| r.end - r.start).sum::<Value>(),
+            14 + 13
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let almanac = Almanac::from(test_input());
+        let composed = almanac.compose().unwrap();
+
+        assert_eq!(composed.source_category, Category::Seed);
+        assert_eq!(composed.destination_category, Category::Location);
+        assert!(composed.map.windows(2).all(|w| w[0].source < w[1].source));
+        for seed in 0..200 {
+            assert_eq!(
+                composed.find_destination_for(seed),
+                almanac.find_location_for_seed(&seed).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invert() {
+        let seed_map = SeedMap {
+            source_category: Category::Seed,
+            destination_category: Category::Soil,
+            map: vec![SeedRange::new(98, 50, 2), SeedRange::new(50, 52, 48)],
+        };
+        let inverted = seed_map.invert().unwrap();
+        assert_eq!(inverted.source_category, Category::Soil);
+        assert_eq!(inverted.destination_category, Category::Seed);
+        for seed in 0..200 {
+            assert_eq!(
+                inverted.find_destination_for(seed_map.find_destination_for(seed)),
+                seed
+            );
+        }
+
+        // Both 0 and 10 end up at 10, so there is no way back.
+        let seed_map = SeedMap {
+            source_category: Category::Seed,
+            destination_category: Category::Soil,
+            map: vec![SeedRange::new(0, 10, 5)],
+        };
+        assert_eq!(seed_map.invert(), None);
+
+        let almanac = Almanac::from(test_input());
+        assert_eq!(almanac.find_seed_for_location(46), Ok(Some(82)));
+        assert_eq!(almanac.find_seed_for_location(35), Ok(Some(13)));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let seed_map = SeedMap {
+            source_category: Category::Seed,
+            destination_category: Category::Soil,
+            map: vec![
+                SeedRange::new(0, 10, 5),
+                SeedRange::new(3, 13, 5),
+                SeedRange::new(7, 0, 2),
+                SeedRange::new(20, 30, 5),
+            ],
+        };
+        assert_eq!(
+            seed_map.conflicts(),
+            vec![
+                RangeConflict::Overlapping(SeedRange::new(0, 10, 5), SeedRange::new(3, 13, 5)),
+                RangeConflict::Contradictory(SeedRange::new(3, 13, 5), SeedRange::new(7, 0, 2)),
+            ]
+        );
+
+        let almanac = Almanac::from(test_input());
+        assert!(almanac.maps.iter().all(|m| m.conflicts().is_empty()));
+    }
+
+    #[test]
+    fn test_custom_category() {
+        assert_eq!(Category::try_from("seed"), Ok(Category::Seed));
+        assert_eq!(
+            Category::try_from("planet"),
+            Ok(Category::Custom("planet".to_string()))
+        );
+        assert_eq!(Category::try_from(""), Err(()));
+        assert_eq!(Category::try_from("two words"), Err(()));
+        assert_eq!(Category::Custom("planet".to_string()).to_string(), "planet");
+        assert_eq!(Category::Humidity.to_string(), "humidity");
+    }
+
+    #[test]
+    fn test_route() {
+        let mut input = test_input();
+        input.extend(
+            r#"
+location-to-planet map:
+100 80 10
+
+moon-to-star map:
+1 2 3"#
+                .lines()
+                .map(String::from),
+        );
+        let almanac = Almanac::from(input);
+        let planet = Category::Custom("planet".to_string());
+        let moon = Category::Custom("moon".to_string());
+
+        assert_eq!(almanac.map(79, &Category::Seed, &Category::Soil), Ok(81));
+        assert_eq!(
+            almanac.map(79, &Category::Seed, &Category::Location),
+            Ok(82)
+        );
+        assert_eq!(almanac.map(79, &Category::Seed, &planet), Ok(102));
+        assert_eq!(almanac.map(14, &Category::Seed, &planet), Ok(43));
+        assert_eq!(almanac.map(79, &Category::Soil, &Category::Soil), Ok(79));
+        assert_eq!(
+            almanac.map(79, &Category::Seed, &moon),
+            Err(RouteError::Disconnected(Category::Seed, moon.clone()))
+        );
+        // There are no maps going back to seeds.
+        assert_eq!(
+            almanac.map(82, &Category::Location, &Category::Seed),
+            Err(RouteError::Disconnected(Category::Location, Category::Seed))
+        );
+        let comet = Category::Custom("comet".to_string());
+        assert_eq!(
+            almanac.route(&Category::Seed, &comet),
+            Err(RouteError::UnknownCategory(comet))
+        );
+
+        // Without a chain from seeds to locations there are no answers.
+        let almanac = Almanac::from(
+            "seeds: 79 14\n\nseed-to-soil map:\n50 98 2\n\nwater-to-location map:\n1 2 3"
+                .lines()
+                .map(String::from)
+                .collect::<Vec<String>>(),
+        );
+        let disconnected = Err(RouteError::Disconnected(Category::Seed, Category::Location));
+        assert_eq!(almanac.find_location_for_seed(&79), disconnected.clone());
+        assert_eq!(
+            almanac.find_lowest_location(),
+            disconnected.clone().map(Some)
+        );
+        assert_eq!(
+            almanac.find_lowest_location_with_seed_range(),
+            Err(SeedRangeError::Route(RouteError::Disconnected(
+                Category::Seed,
+                Category::Location
+            )))
+        );
+        assert_eq!(
+            almanac.find_seed_for_location(46),
+            disconnected.clone().map(Some)
+        );
+        let almanac = Almanac::from(vec!["seeds: 79 14".to_string()]);
+        assert_eq!(
+            almanac.compose(),
+            Err(RouteError::UnknownCategory(Category::Seed))
+        );
+        assert_eq!(
+            RouteError::Disconnected(Category::Seed, moon).to_string(),
+            "there is no way to get from `seed` to `moon`"
+        );
+    }
+
+    #[test]
+    fn test_maps_out_of_order() {
+        // Move the first map to the end of the almanac.
+        let mut input = test_input();
+        let seed_to_soil = input.drain(2..6).collect::<Vec<String>>();
+        input.push(String::new());
+        input.extend(seed_to_soil.into_iter().take(3));
+        let almanac = Almanac::from(input);
+
+        assert_eq!(almanac.maps[0].source_category, Category::Soil);
+        assert_eq!(almanac.find_lowest_location(), Ok(Some(35)));
+        assert_eq!(almanac.find_lowest_location_with_seed_range(), Ok(46));
+    }
+
+    #[test]
+    fn test_parse_formatting_variants() {
+        let expected = Almanac::from(test_input());
+
+        // CRLF, trailing whitespace, several blank lines, multiple spaces between numbers
+        // and seeds on more than one line.
+        let input = "seeds: 79  14\r\n   55 13  \r\n\r\n\r\n".to_string()
+            + &test_input()[2..]
+                .iter()
+                .map(|line| format!("{}  \r\n\r\n", line.replace(' ', "   ")))
+                .collect::<String>();
+        let lines = input.split('\n').map(String::from).collect::<Vec<String>>();
+        let almanac = Almanac::parse(&lines).unwrap();
+
+        assert_eq!(almanac.seeds, expected.seeds);
+        assert_eq!(almanac.maps, expected.maps);
+        assert_eq!(almanac.find_lowest_location(), Ok(Some(35)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse =
+            |input: &str| Almanac::parse(&input.lines().map(String::from).collect::<Vec<_>>());
+
+        assert_eq!(
+            parse("seed-to-soil map:\n1 2 3").unwrap_err(),
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::MissingSeeds
+            }
+        );
+        assert_eq!(
+            parse("seeds: 1 2\n\nseed-to-soil map:\n1 2\n").unwrap_err(),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::InvalidRange(2)
+            }
+        );
+        assert_eq!(
+            parse("seeds: 1 x2").unwrap_err().kind,
+            ParseErrorKind::InvalidNumber("x2".to_string())
+        );
+        assert_eq!(
+            parse("seeds: 1 2\nseed to soil map:").unwrap_err(),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::InvalidHeader("seed to soil map:".to_string())
+            }
+        );
+        assert_eq!(
+            parse("seeds: 1 2\nseeds: 3 4").unwrap_err().kind,
+            ParseErrorKind::DuplicateSeeds
+        );
+        assert_eq!(
+            parse("\n1 2 3\nseeds: 1 2").unwrap_err(),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::UnexpectedLine
+            }
+        );
+        assert_eq!(
+            parse("seeds: 1 2\n\nseed-to-soil map:\n1 2 3 4")
+                .unwrap_err()
+                .to_string(),
+            "line 4: expected 3 numbers for a range, found 4"
+        );
+    }
+
+    #[test]
+    fn test_odd_seeds() {
+        let mut input = test_input();
+        input[0] = "seeds: 79 14 55".to_string();
+        let almanac = Almanac::from(input);
+
+        assert_eq!(almanac.find_lowest_location(), Ok(Some(43)));
+        assert_eq!(almanac.seed_ranges(), Err(SeedRangeError::UnpairedSeed(55)));
+        assert_eq!(
+            almanac.find_lowest_location_with_seed_range(),
+            Err(SeedRangeError::UnpairedSeed(55))
+        );
+
+        // Empty intervals don't have a lowest location.
+        let mut input = test_input();
+        input[0] = "seeds: 79 0 14 0".to_string();
+        let almanac = Almanac::from(input);
+        assert_eq!(almanac.seed_ranges(), Ok(vec![79..79, 14..14]));
+        assert_eq!(
+            almanac.find_lowest_location_with_seed_range(),
+            Err(SeedRangeError::EmptyRanges)
+        );
+        assert_eq!(
+            SeedRangeError::EmptyRanges.to_string(),
+            "all the seed ranges are empty"
+        );
+    }
+
+    #[test]
+    fn test_near_u64_max() {
+        const MAX: Value = u64::MAX as Value;
+        let input = format!(
+            r#"seeds: {} 2 0 3
+
+seed-to-soil map:
+0 {} 2
+{} 0 2
+
+soil-to-location map:
+{} 0 1"#,
+            MAX - 1,
+            MAX - 1,
+            MAX - 1,
+            MAX
+        );
+        let almanac = Almanac::from(input.lines().map(String::from).collect::<Vec<String>>());
+
+        assert_eq!(almanac.maps[0].map[0].sources(), MAX - 1..MAX + 1);
+        assert_eq!(almanac.find_location_for_seed(&MAX), Ok(1));
+        assert_eq!(almanac.find_location_for_seed(&(MAX - 1)), Ok(MAX));
+        assert_eq!(almanac.find_location_for_seed(&0), Ok(MAX - 1));
+        assert_eq!(almanac.find_location_for_seed(&1), Ok(MAX));
+        assert_eq!(almanac.find_location_for_seed(&2), Ok(2));
+        assert_eq!(
+            almanac.maps[0].find_destinations_for_range(MAX - 3..MAX + 1),
+            vec![MAX - 3..MAX - 1, 0..2]
+        );
+        assert_eq!(
+            almanac.find_location_ranges_for(&almanac.seed_ranges().unwrap()),
+            Ok(vec![MAX..MAX + 1, 1..2, MAX - 1..MAX + 1, 2..3])
+        );
+        assert_eq!(almanac.find_lowest_location_with_seed_range(), Ok(1));
+
+        let composed = almanac.compose().unwrap();
+        for seed in [0, 1, 2, MAX - 2, MAX - 1, MAX] {
+            assert_eq!(
+                composed.find_destination_for(seed),
+                almanac.find_location_for_seed(&seed).unwrap()
+            );
+        }
+
+        // Numbers that don't fit in a u64 are rejected.
+        let input = format!("seeds: {}0", u64::MAX);
+        assert_eq!(
+            Almanac::parse(&[input]).unwrap_err().kind,
+            ParseErrorKind::InvalidNumber(format!("{}0", u64::MAX))
+        );
     }
 }
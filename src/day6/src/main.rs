@@ -1,95 +1,326 @@
+use std::env;
+use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
-use utils::{as_vec, read_lines};
+use utils::read_lines;
 
-fn number_from_str(input: &str) -> usize {
-    input
-        .chars()
-        .filter(|c| c.is_ascii_digit())
-        .collect::<String>()
-        .parse::<usize>()
-        .unwrap()
+// How the boat picks up speed while the button is held, and how it moves afterwards.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Acceleration {
+    // Every ms the button is held adds `rate` mm/ms to the speed of the boat. The puzzle
+    // boats have a rate of 1.
+    Linear { rate: u128 },
+    // Like `Linear`, but the speed can't go above `max_speed`.
+    Capped { rate: u128, max_speed: u128 },
+    // Like `Linear`, but once released the boat loses `friction` mm/ms of speed every ms
+    // until it stops.
+    Friction { rate: u128, friction: u128 },
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Acceleration::Linear { rate: 1 }
+    }
+}
+
+impl TryFrom<&[String]> for Acceleration {
+    type Error = ();
+
+    // Parses `linear <rate>`, `capped <rate> <max speed>` or `friction <rate> <friction>`.
+    fn try_from(value: &[String]) -> Result<Self, Self::Error> {
+        let (name, numbers) = value.split_first().ok_or(())?;
+        let numbers = numbers
+            .iter()
+            .map(|n| n.parse::<u128>().map_err(|_| ()))
+            .collect::<Result<Vec<u128>, ()>>()?;
+        match (name.as_str(), numbers.as_slice()) {
+            ("linear", [rate]) => Ok(Acceleration::Linear { rate: *rate }),
+            ("capped", [rate, max_speed]) => Ok(Acceleration::Capped {
+                rate: *rate,
+                max_speed: *max_speed,
+            }),
+            ("friction", [rate, friction]) => Ok(Acceleration::Friction {
+                rate: *rate,
+                friction: *friction,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Acceleration {
+    // Whether the boat never gets any speed, in which case it always travels 0 mm.
+    fn never_moves(&self) -> bool {
+        match *self {
+            Acceleration::Linear { rate } | Acceleration::Friction { rate, .. } => rate == 0,
+            Acceleration::Capped { rate, max_speed } => rate == 0 || max_speed == 0,
+        }
+    }
+
+    // Returns the distance travelled in a race of `time` ms when holding the button for
+    // `time_pressed` ms, or `None` when it doesn't fit in a u128.
+    fn distance(&self, time: u128, time_pressed: u128) -> Option<u128> {
+        let travel_time = time.saturating_sub(time_pressed);
+        // Without any time left the boat doesn't move, however fast it would go.
+        if travel_time == 0 {
+            return Some(0);
+        }
+        match *self {
+            Acceleration::Linear { rate } => {
+                rate.checked_mul(time_pressed)?.checked_mul(travel_time)
+            }
+            Acceleration::Capped { rate, max_speed } => {
+                let speed = rate
+                    .checked_mul(time_pressed)
+                    .map_or(max_speed, |speed| speed.min(max_speed));
+                speed.checked_mul(travel_time)
+            }
+            Acceleration::Friction { rate, friction } => {
+                let speed = rate.checked_mul(time_pressed)?;
+                if friction == 0 {
+                    return speed.checked_mul(travel_time);
+                }
+                // The boat moves for as long as its speed is positive, or until the race ends.
+                let moving_time = travel_time.min(speed.div_ceil(friction));
+                if moving_time <= 1 {
+                    return Some(speed * moving_time);
+                }
+                // The speed goes down linearly, so the distance is the number of ms the boat
+                // moves times the average of the first and the last speed.
+                let last_speed = speed - friction * (moving_time - 1);
+                let speed_sum = speed.checked_add(last_speed)?;
+                if moving_time % 2 == 0 {
+                    (moving_time / 2).checked_mul(speed_sum)
+                } else {
+                    moving_time.checked_mul(speed_sum / 2)
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 struct Race {
-    time: usize,
-    distance: usize,
+    time: u128,
+    distance: u128,
+    acceleration: Acceleration,
 }
 
 impl Race {
-    fn new(time: usize, distance: usize) -> Self {
-        Self { time, distance }
+    fn new(time: u128, distance: u128) -> Self {
+        Self {
+            time,
+            distance,
+            acceleration: Acceleration::default(),
+        }
     }
 
-    fn count_possible_wins(&self) -> usize {
-        let first_win = self.find_lowest_button_hold().unwrap();
-        let last_win = self.find_highest_button_hold(first_win).unwrap();
-        last_win - first_win + 1
+    fn with_acceleration(mut self, acceleration: Acceleration) -> Self {
+        self.acceleration = acceleration;
+        self
     }
 
-    fn is_winning_distance(&self, dist: usize) -> bool {
-        self.distance < dist
+    fn count_possible_wins(&self) -> u128 {
+        match self.winning_interval() {
+            Some((lo, hi)) => hi - lo + 1,
+            None => 0,
+        }
     }
 
-    fn calculate_distance_for_time_pressed(&self, time_pressed: usize) -> usize {
-        self.time.saturating_sub(time_pressed) * time_pressed
+    // Returns `None` when the distance doesn't fit in a u128, which is more than any record.
+    fn calculate_distance_for_time_pressed(&self, time_pressed: u128) -> Option<u128> {
+        self.acceleration.distance(self.time, time_pressed)
     }
 
-    // find highest time to hold the button such that it yields a distance
-    // greater than the record. we don't need to start at 0, we just start
-    // at the minimum button press that yields a win.
-    fn find_highest_button_hold(&self, start: usize) -> Option<usize> {
-        let mut s = start;
-        let mut end = self.time;
-        let mut mid;
-        loop {
-            mid = (s + end) / 2;
-            if s > end {
-                return None;
+    fn is_winning_hold(&self, time_pressed: u128) -> bool {
+        match self.calculate_distance_for_time_pressed(time_pressed) {
+            Some(distance) => distance > self.distance,
+            None => true,
+        }
+    }
+
+    // Returns the interval [lo, hi] of button holds that beat the record, or `None` when
+    // the race can't be won.
+    fn winning_interval(&self) -> Option<(u128, u128)> {
+        // The distance is 0 for every hold, which can't beat any record. This is also the
+        // worst case for `is_past_peak`.
+        if self.acceleration.never_moves() {
+            return None;
+        }
+        match self.acceleration {
+            // rate * h * (time - h) > distance is the same as h * (time - h) > distance / rate,
+            // because h * (time - h) is an integer. That's the puzzle race with a lower record.
+            Acceleration::Linear { rate } => {
+                Race::new(self.time, self.distance / rate).quadratic_winning_interval()
             }
-            let distance = self.calculate_distance_for_time_pressed(mid);
-            if self.is_winning_distance(distance) {
-                if !self.is_winning_distance(self.calculate_distance_for_time_pressed(mid + 1)) {
-                    return Some(mid);
-                } else {
-                    s = mid + 1;
-                }
+            _ => self
+                .unimodal_winning_interval(|hold| self.calculate_distance_for_time_pressed(hold)),
+        }
+    }
+
+    // Only works for the puzzle boats, that have a linear acceleration with a rate of 1.
+    // Holding the button for `h` ms wins when h * (time - h) > distance, which means that
+    // `h` must be strictly between the roots of h^2 - time * h + distance = 0. The distance
+    // is symmetric around time / 2, so hi = time - lo.
+    fn quadratic_winning_interval(&self) -> Option<(u128, u128)> {
+        let half = self.time / 2;
+        if !self.is_winning_hold(half) {
+            return None;
+        }
+
+        let mut lo = match self.time.checked_mul(self.time) {
+            // The smaller root is (time - sqrt(time^2 - 4 * distance)) / 2. The race is
+            // winnable, so the discriminant is positive and 4 * distance fits in a u128.
+            Some(square) => (self.time - (square - 4 * self.distance).isqrt()) / 2,
+            // time^2 doesn't fit in a u128, so we can't compute the discriminant. In this
+            // case we binary search the first winning hold in [0, time / 2], where the
+            // distance is increasing.
+            None => self.first_hold_where(0, half, |hold| self.is_winning_hold(hold)),
+        };
+        // The integer square root rounds down, so `lo` can be off by one in either direction.
+        while lo > 0 && self.is_winning_hold(lo - 1) {
+            lo -= 1;
+        }
+        while !self.is_winning_hold(lo) {
+            lo += 1;
+        }
+        Some((lo, self.time - lo))
+    }
+
+    // Works for any `distance` that never goes down until it reaches its maximum, and never
+    // goes up afterwards. `distance` returns `None` when it doesn't fit in a u128.
+    fn unimodal_winning_interval<F: Fn(u128) -> Option<u128>>(
+        &self,
+        distance: F,
+    ) -> Option<(u128, u128)> {
+        let distance = |hold| distance(hold).unwrap_or(u128::MAX);
+        let wins = |hold| distance(hold) > self.distance;
+        // The first hold after which the distance doesn't go up anymore is the best one.
+        let best = self.first_hold_where(0, self.time, |hold| self.is_past_peak(hold, distance));
+        if !wins(best) {
+            return None;
+        }
+        let lo = self.first_hold_where(0, best, wins);
+        // `best` is a win, so the first hold after it that is not a win is always > `best`.
+        let hi =
+            self.first_hold_where(best, self.time, |hold| hold == self.time || !wins(hold + 1));
+        Some((lo, hi))
+    }
+
+    // Whether the distance goes down after `hold`, or stays the same until the end of the
+    // race. The holds for which the distance is the same as for `hold` don't tell whether
+    // we are before or after the peak, so we skip them one by one. This is slow if the
+    // distance is the same for many holds in a row.
+    fn is_past_peak<F: Fn(u128) -> u128>(&self, hold: u128, distance: F) -> bool {
+        let current = distance(hold);
+        let mut next = hold + 1;
+        while next <= self.time && distance(next) == current {
+            next += 1;
+        }
+        next > self.time || distance(next) < current
+    }
+
+    // Binary searches the first hold in [start, end] for which `predicate` is true. The
+    // predicate must be false up to some hold and true from there on, and true for `end`.
+    fn first_hold_where<F: Fn(u128) -> bool>(
+        &self,
+        mut start: u128,
+        mut end: u128,
+        predicate: F,
+    ) -> u128 {
+        while start < end {
+            let mid = start + (end - start) / 2;
+            if predicate(mid) {
+                end = mid;
             } else {
-                end = mid - 1;
+                start = mid + 1;
             }
         }
+        start
     }
+}
 
-    fn find_lowest_button_hold(&self) -> Option<usize> {
-        let mut s = 0;
-        let mut end = self.time;
-        let mut mid;
+// How well a single button hold does in a race.
+#[derive(Debug, Clone, PartialEq)]
+struct HoldStats {
+    hold: u128,
+    // Saturates at u128::MAX for distances that don't fit.
+    distance: u128,
+    // How much further than the record the boat goes, negative when it doesn't win.
+    // Saturates at the limits of an i128, so it is only used for printing.
+    margin: i128,
+    wins: bool,
+}
 
-        loop {
-            mid = (s + end) / 2;
-            if s > end {
-                return None;
-            }
+// The distance that the boat travels for every possible hold in a race.
+#[derive(Debug, Clone, PartialEq)]
+struct RaceAnalysis {
+    race: Race,
+    curve: Vec<HoldStats>,
+    // The hold that takes the boat the furthest. If more holds are equally good, this is
+    // the shortest of them.
+    best_hold: u128,
+}
 
-            let distance = self.calculate_distance_for_time_pressed(mid);
-            if self.is_winning_distance(distance) {
-                if !self.is_winning_distance(self.calculate_distance_for_time_pressed(mid - 1)) {
-                    return Some(mid);
-                } else {
-                    end = mid - 1;
+impl RaceAnalysis {
+    fn new(race: &Race) -> Self {
+        let record = i128::try_from(race.distance).unwrap_or(i128::MAX);
+        let curve = (0..=race.time)
+            .map(|hold| {
+                let distance = race
+                    .calculate_distance_for_time_pressed(hold)
+                    .unwrap_or(u128::MAX);
+                let margin = i128::try_from(distance)
+                    .unwrap_or(i128::MAX)
+                    .saturating_sub(record);
+                HoldStats {
+                    hold,
+                    distance,
+                    margin,
+                    wins: race.is_winning_hold(hold),
                 }
-            } else {
-                s = mid + 1;
-            }
+            })
+            .collect::<Vec<HoldStats>>();
+        // `max_by_key` returns the last maximum, and we want the first one.
+        let best_hold = curve
+            .iter()
+            .rev()
+            .max_by_key(|stats| stats.distance)
+            .unwrap()
+            .hold;
+        Self {
+            race: race.clone(),
+            curve,
+            best_hold,
         }
     }
+
+    fn count_wins(&self) -> usize {
+        self.curve.iter().filter(|stats| stats.wins).count()
+    }
 }
 
-impl From<Vec<String>> for Race {
-    fn from(value: Vec<String>) -> Self {
-        let time = number_from_str(value[0].split_once(':').unwrap().1);
-        let distance = number_from_str(value[1].split_once(':').unwrap().1);
-        Self { time, distance }
+impl Display for RaceAnalysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Time: {} ms, record: {} mm, best hold: {} ms, ways to win: {}",
+            self.race.time,
+            self.race.distance,
+            self.best_hold,
+            self.count_wins()
+        )?;
+        writeln!(f, "{:>8} {:>12} {:>12} win", "hold", "distance", "margin")?;
+        for stats in self.curve.iter() {
+            writeln!(
+                f,
+                "{:>8} {:>12} {:>12} {}",
+                stats.hold,
+                stats.distance,
+                stats.margin,
+                if stats.wins { "yes" } else { "no" }
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -98,36 +329,279 @@ struct Races {
     inner: Vec<Race>,
 }
 
+// What went wrong while parsing a race sheet.
+#[derive(Debug, Clone, PartialEq)]
+enum ParseErrorKind {
+    // There is no `Time:` row.
+    MissingTimes,
+    // There is more than one `Time:` row.
+    DuplicateTimes,
+    // There is no `Distance:` row.
+    MissingRecords,
+    // Rows must start with `Time:` or `Distance:`.
+    UnknownRow(String),
+    // A row without any numbers.
+    EmptyRow,
+    InvalidNumber(String),
+    // The numbers of a row are too big once the spaces between them are removed.
+    KernedOverflow(String),
+    // A row doesn't have the same number of columns as the `Time:` row.
+    ColumnCount { expected: usize, found: usize },
+}
+
+type ParseError = utils::ParseError<ParseErrorKind>;
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::MissingTimes => write!(f, "the sheet has no `Time:` row"),
+            ParseErrorKind::DuplicateTimes => write!(f, "the times are already defined"),
+            ParseErrorKind::MissingRecords => write!(f, "the sheet has no `Distance:` row"),
+            ParseErrorKind::UnknownRow(row) => write!(f, "unknown row `{row}`"),
+            ParseErrorKind::EmptyRow => write!(f, "the row has no numbers"),
+            ParseErrorKind::InvalidNumber(token) => write!(f, "`{token}` is not a valid number"),
+            ParseErrorKind::KernedOverflow(number) => {
+                write!(f, "`{number}` doesn't fit in a u128")
+            }
+            ParseErrorKind::ColumnCount { expected, found } => {
+                write!(f, "expected {expected} columns, found {found}")
+            }
+        }
+    }
+}
+
+// The sheet of paper with the race times and the record distances. The columns can be read
+// either as separate races, or as a single race with bad kerning.
+#[derive(Debug, Clone, PartialEq)]
+struct RaceSheet {
+    times: Vec<u128>,
+    // There can be more than one `Distance:` row, in which case each race has more than one
+    // record. To win a race, the boat has to beat all of them, so we only keep the best one.
+    records: Vec<u128>,
+    // The single race read with the spaces between the columns ignored. All the columns
+    // can fit in a u128 while the whole row doesn't, which only matters for this race.
+    kerned: Result<Race, ParseError>,
+}
+
+// The numbers of a row, and the number that they make when the spaces are ignored.
+struct Row {
+    line: usize,
+    columns: Vec<u128>,
+    kerned: Result<u128, ParseError>,
+}
+
+impl RaceSheet {
+    fn parse(lines: &[String]) -> Result<Self, ParseError> {
+        let mut times: Option<Row> = None;
+        let mut records: Vec<Row> = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let error = |kind| ParseError {
+                line: index + 1,
+                kind,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (row, values) = line
+                .split_once(':')
+                .ok_or_else(|| error(ParseErrorKind::UnknownRow(line.to_string())))?;
+            let tokens = values.split_whitespace().collect::<Vec<&str>>();
+            let columns = tokens
+                .iter()
+                .map(|token| {
+                    token
+                        .parse::<u128>()
+                        .map_err(|_| error(ParseErrorKind::InvalidNumber(token.to_string())))
+                })
+                .collect::<Result<Vec<u128>, ParseError>>()?;
+            if columns.is_empty() {
+                return Err(error(ParseErrorKind::EmptyRow));
+            }
+            // The tokens are valid numbers, so the only way for them not to be a valid number
+            // together is to be too big.
+            let kerned = tokens
+                .concat()
+                .parse::<u128>()
+                .map_err(|_| error(ParseErrorKind::KernedOverflow(tokens.concat())));
+            let parsed = Row {
+                line: index + 1,
+                columns,
+                kerned,
+            };
+
+            match row.trim() {
+                "Time" if times.is_some() => return Err(error(ParseErrorKind::DuplicateTimes)),
+                "Time" => times = Some(parsed),
+                "Distance" => records.push(parsed),
+                _ => return Err(error(ParseErrorKind::UnknownRow(row.to_string()))),
+            }
+        }
+
+        let times = times.ok_or(ParseError {
+            line: 1,
+            kind: ParseErrorKind::MissingTimes,
+        })?;
+        if records.is_empty() {
+            return Err(ParseError {
+                line: lines.len(),
+                kind: ParseErrorKind::MissingRecords,
+            });
+        }
+        let mut best_records = vec![0; times.columns.len()];
+        for row in records.iter() {
+            if row.columns.len() != times.columns.len() {
+                return Err(ParseError {
+                    line: row.line,
+                    kind: ParseErrorKind::ColumnCount {
+                        expected: times.columns.len(),
+                        found: row.columns.len(),
+                    },
+                });
+            }
+            for (best, record) in best_records.iter_mut().zip(row.columns.iter()) {
+                *best = u128::max(*best, *record);
+            }
+        }
+        let kerned = times.kerned.and_then(|time| {
+            let distance = records
+                .into_iter()
+                .try_fold(0, |best, row| Ok(u128::max(best, row.kerned?)))?;
+            Ok(Race::new(time, distance))
+        });
+        Ok(Self {
+            times: times.columns,
+            records: best_records,
+            kerned,
+        })
+    }
+
+    // Each column is a separate race.
+    fn races(&self) -> Races {
+        let inner = self
+            .times
+            .iter()
+            .zip(self.records.iter())
+            .map(|(time, record)| Race::new(*time, *record))
+            .collect();
+        Races { inner }
+    }
+
+    // The spaces between the columns are just bad kerning, so there is a single race. Fails
+    // if the numbers are too big without the spaces.
+    fn single_race(&self) -> Result<Race, ParseError> {
+        self.kerned.clone()
+    }
+}
+
+impl From<Vec<String>> for Race {
+    fn from(value: Vec<String>) -> Self {
+        RaceSheet::parse(&value).unwrap().single_race().unwrap()
+    }
+}
+
 impl From<Vec<String>> for Races {
     fn from(input: Vec<String>) -> Self {
-        let times: Vec<usize> = as_vec(input[0].split_once(':').unwrap().1);
-        let distances: Vec<usize> = as_vec(input[1].split_once(':').unwrap().1);
-        let races = times
-            .iter()
-            .zip(distances.iter())
-            .map(|(time, dist)| Race::new(*time, *dist))
-            .collect::<Vec<Race>>();
-        Self { inner: races }
+        RaceSheet::parse(&input).unwrap().races()
     }
 }
 
 impl Races {
-    fn multiply_wins(&self) -> usize {
+    fn with_acceleration(self, acceleration: Acceleration) -> Self {
+        Self {
+            inner: self
+                .inner
+                .into_iter()
+                .map(|race| race.with_acceleration(acceleration))
+                .collect(),
+        }
+    }
+
+    fn multiply_wins(&self) -> u128 {
         self.inner
             .iter()
             .map(|race| race.count_possible_wins())
-            .product::<usize>()
+            .product::<u128>()
+    }
+
+    // This goes through every possible hold, so it should only be used for short races.
+    fn analyze(&self) -> Vec<RaceAnalysis> {
+        self.inner.iter().map(RaceAnalysis::new).collect()
+    }
+
+    fn to_table(&self) -> String {
+        self.analyze()
+            .iter()
+            .enumerate()
+            .map(|(index, analysis)| format!("Race {}\n{analysis}", index + 1))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("race,time,record,hold,distance,margin,wins\n");
+        for (index, analysis) in self.analyze().iter().enumerate() {
+            for stats in analysis.curve.iter() {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    index + 1,
+                    analysis.race.time,
+                    analysis.race.distance,
+                    stats.hold,
+                    stats.distance,
+                    stats.margin,
+                    stats.wins
+                ));
+            }
+        }
+        csv
     }
 }
 
 fn main() {
     let path = PathBuf::from("src/day6/src/input.txt");
     let input = read_lines(path);
-    let races = Races::from(input.clone());
+
+    // The boats can be changed by passing an acceleration model, e.g. `capped 1 20`, and
+    // the races of part 1 can be printed with `--table` or `--csv`.
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let acceleration = if args.is_empty() {
+        Acceleration::default()
+    } else {
+        match Acceleration::try_from(args.as_slice()) {
+            Ok(acceleration) => acceleration,
+            Err(_) => {
+                eprintln!("Usage: day6 [--table | --csv] [linear <rate> | capped <rate> <max speed> | friction <rate> <friction>]");
+                return;
+            }
+        }
+    };
+
+    let sheet = match RaceSheet::parse(&input) {
+        Ok(sheet) => sheet,
+        Err(e) => {
+            eprintln!("Invalid race sheet: {e}");
+            return;
+        }
+    };
+    let races = sheet.races().with_acceleration(acceleration);
     println!("Part 1: {}", races.multiply_wins());
+    if flags.iter().any(|flag| flag == "--table") {
+        println!("{}", races.to_table());
+    }
+    if flags.iter().any(|flag| flag == "--csv") {
+        print!("{}", races.to_csv());
+    }
 
-    let race = Race::from(input);
-    println!("Part 2: {}", race.count_possible_wins());
+    match sheet.single_race() {
+        Ok(race) => println!(
+            "Part 2: {}",
+            race.with_acceleration(acceleration).count_possible_wins()
+        ),
+        Err(e) => eprintln!("Part 2: {e}"),
+    }
 }
 
 #[cfg(test)]
@@ -150,8 +624,53 @@ mod tests {
     #[test]
     fn test_find_interval() {
         let race = Race::new(7, 9);
-        assert_eq!(race.find_lowest_button_hold().unwrap(), 2);
-        assert_eq!(race.find_highest_button_hold(2).unwrap(), 5);
+        assert_eq!(race.winning_interval(), Some((2, 5)));
+        assert_eq!(Race::new(15, 40).winning_interval(), Some((4, 11)));
+        assert_eq!(Race::new(30, 200).winning_interval(), Some((11, 19)));
+
+        // Compare with the winning holds we get by trying all of them.
+        for time in 0..40 {
+            for distance in 0..400 {
+                let race = Race::new(time, distance);
+                let wins = (0..=time)
+                    .filter(|hold| (time - hold) * hold > distance)
+                    .collect::<Vec<u128>>();
+                let expected = wins.first().map(|lo| (*lo, *wins.last().unwrap()));
+                assert_eq!(race.winning_interval(), expected, "{race:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_wins() {
+        // The best we can do is to hold for 2 ms and get to 4 mm, which is not a win.
+        assert_eq!(Race::new(4, 4).winning_interval(), None);
+        assert_eq!(Race::new(4, 4).count_possible_wins(), 0);
+        assert_eq!(Race::new(0, 0).count_possible_wins(), 0);
+        assert_eq!(Race::new(1, 0).count_possible_wins(), 0);
+        assert_eq!(Race::new(10, u128::MAX).count_possible_wins(), 0);
+        let races = Races {
+            inner: vec![Race::new(7, 9), Race::new(4, 4)],
+        };
+        assert_eq!(races.multiply_wins(), 0);
+    }
+
+    #[test]
+    fn test_large_races() {
+        // time^2 still fits in a u128.
+        let time = u64::MAX as u128;
+        let race = Race::new(time, time);
+        assert_eq!(race.winning_interval(), Some((2, time - 2)));
+        let race = Race::new(time, (time / 2) * (time - time / 2) - 1);
+        assert_eq!(race.winning_interval(), Some((time / 2, time - time / 2)));
+
+        // time^2 doesn't fit in a u128 anymore.
+        let race = Race::new(u128::MAX, u128::MAX);
+        assert_eq!(race.winning_interval(), Some((2, u128::MAX - 2)));
+        assert_eq!(race.count_possible_wins(), u128::MAX - 3);
+        let time = 1u128 << 64;
+        let race = Race::new(time, 3 * (time - 3));
+        assert_eq!(race.winning_interval(), Some((4, time - 4)));
     }
 
     #[test]
@@ -173,4 +692,309 @@ mod tests {
         let races = Race::from(input);
         assert_eq!(races.count_possible_wins(), 71503);
     }
+
+    // Finds the winning interval by trying all the holds.
+    fn brute_force_interval(race: &Race) -> Option<(u128, u128)> {
+        let wins = (0..=race.time)
+            .filter(|hold| race.is_winning_hold(*hold))
+            .collect::<Vec<u128>>();
+        // The winning holds must be consecutive.
+        if let (Some(lo), Some(hi)) = (wins.first(), wins.last()) {
+            assert_eq!(hi - lo + 1, wins.len() as u128, "{race:?}");
+        }
+        wins.first().map(|lo| (*lo, *wins.last().unwrap()))
+    }
+
+    #[test]
+    fn test_acceleration_distance() {
+        let linear = Acceleration::Linear { rate: 2 };
+        assert_eq!(linear.distance(7, 3), Some(24));
+        let capped = Acceleration::Capped {
+            rate: 2,
+            max_speed: 5,
+        };
+        assert_eq!(capped.distance(7, 2), Some(20));
+        assert_eq!(capped.distance(7, 3), Some(20));
+        assert_eq!(capped.distance(7, 7), Some(0));
+        // Speed 6, then 4, 2 and the boat stops.
+        let friction = Acceleration::Friction {
+            rate: 2,
+            friction: 2,
+        };
+        assert_eq!(friction.distance(10, 3), Some(12));
+        // Speed 4, then 2, and the race is over.
+        assert_eq!(friction.distance(4, 2), Some(6));
+        assert_eq!(friction.distance(4, 4), Some(0));
+        assert_eq!(Acceleration::default().distance(u128::MAX, 2), None);
+        let fast = Acceleration::Linear { rate: u128::MAX };
+        assert_eq!(fast.distance(3, 3), Some(0));
+        assert_eq!(fast.distance(3, 2), None);
+    }
+
+    #[test]
+    fn test_acceleration_models() {
+        let models = [
+            Acceleration::Linear { rate: 0 },
+            Acceleration::Linear { rate: 3 },
+            Acceleration::Capped {
+                rate: 2,
+                max_speed: 7,
+            },
+            Acceleration::Capped {
+                rate: 5,
+                max_speed: 1,
+            },
+            Acceleration::Friction {
+                rate: 1,
+                friction: 0,
+            },
+            Acceleration::Friction {
+                rate: 3,
+                friction: 2,
+            },
+            Acceleration::Friction {
+                rate: 1,
+                friction: 5,
+            },
+        ];
+        for acceleration in models {
+            for time in 0..30 {
+                for distance in (0..300).step_by(7) {
+                    let race = Race::new(time, distance).with_acceleration(acceleration);
+                    assert_eq!(
+                        race.winning_interval(),
+                        brute_force_interval(&race),
+                        "{race:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_plateau() {
+        // The distance stays the same for a while before going up to its peak.
+        let distances = [0, 3, 3, 3, 3, 3, 3, 8, 9, 6, 0];
+        let race = Race::new(10, 5);
+        let interval = race.unimodal_winning_interval(|hold| Some(distances[hold as usize]));
+        assert_eq!(interval, Some((7, 9)));
+        // A plateau at the peak, and one after it.
+        let distances = [0, 4, 7, 7, 7, 5, 5, 5, 2, 2, 0];
+        let interval = race.unimodal_winning_interval(|hold| Some(distances[hold as usize]));
+        assert_eq!(interval, Some((2, 4)));
+        let race = Race::new(10, 4);
+        let interval = race.unimodal_winning_interval(|hold| Some(distances[hold as usize]));
+        assert_eq!(interval, Some((2, 7)));
+        let race = Race::new(10, 7);
+        let interval = race.unimodal_winning_interval(|hold| Some(distances[hold as usize]));
+        assert_eq!(interval, None);
+    }
+
+    #[test]
+    fn test_parse_acceleration() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<String>>();
+        assert_eq!(
+            Acceleration::try_from(args("capped 1 20").as_slice()),
+            Ok(Acceleration::Capped {
+                rate: 1,
+                max_speed: 20
+            })
+        );
+        assert_eq!(
+            Acceleration::try_from(args("linear 2").as_slice()),
+            Ok(Acceleration::Linear { rate: 2 })
+        );
+        assert_eq!(Acceleration::try_from(args("linear").as_slice()), Err(()));
+        assert_eq!(
+            Acceleration::try_from(args("friction 1 x").as_slice()),
+            Err(())
+        );
+        assert_eq!(Acceleration::try_from(args("rocket 1").as_slice()), Err(()));
+        assert_eq!(Acceleration::try_from(&[] as &[String]), Err(()));
+    }
+
+    #[test]
+    fn test_never_moves() {
+        for acceleration in [
+            Acceleration::Linear { rate: 0 },
+            Acceleration::Capped {
+                rate: 3,
+                max_speed: 0,
+            },
+            Acceleration::Friction {
+                rate: 0,
+                friction: 1,
+            },
+        ] {
+            assert!(acceleration.never_moves());
+            let race = Race::new(50_000_000, 100).with_acceleration(acceleration);
+            assert_eq!(race.winning_interval(), None);
+            assert_eq!(race.count_possible_wins(), 0);
+        }
+        assert!(!Acceleration::default().never_moves());
+    }
+
+    #[test]
+    fn test_race_sheet() {
+        let input = vec![
+            "Time:      7  15   30".to_string(),
+            "".to_string(),
+            "Distance:  9  40  200  ".to_string(),
+        ];
+        let sheet = RaceSheet::parse(&input).unwrap();
+        assert_eq!(sheet.times, vec![7, 15, 30]);
+        assert_eq!(
+            sheet.races().inner,
+            vec![Race::new(7, 9), Race::new(15, 40), Race::new(30, 200)]
+        );
+        assert_eq!(sheet.single_race(), Ok(Race::new(71530, 940200)));
+
+        // Each race must beat the best of its records.
+        let input = vec![
+            "Time:      7  15   30".to_string(),
+            "Distance:  9  40  200".to_string(),
+            "Distance:  6  50  100".to_string(),
+        ];
+        let sheet = RaceSheet::parse(&input).unwrap();
+        assert_eq!(sheet.records, vec![9, 50, 200]);
+        assert_eq!(
+            sheet.races().inner,
+            vec![Race::new(7, 9), Race::new(15, 50), Race::new(30, 200)]
+        );
+        assert_eq!(sheet.single_race(), Ok(Race::new(71530, 940200)));
+    }
+
+    #[test]
+    fn test_race_sheet_errors() {
+        let parse = |input: &str| {
+            RaceSheet::parse(&input.lines().map(String::from).collect::<Vec<String>>()).unwrap_err()
+        };
+
+        assert_eq!(
+            parse("Time:  7  15  30\nDistance:  9  40"),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::ColumnCount {
+                    expected: 3,
+                    found: 2
+                }
+            }
+        );
+        assert_eq!(parse("Distance:  9").kind, ParseErrorKind::MissingTimes);
+        assert_eq!(parse("Time:  7").kind, ParseErrorKind::MissingRecords);
+        assert_eq!(parse("Time:\nDistance:").kind, ParseErrorKind::EmptyRow);
+        assert_eq!(
+            parse("Time:  7\nTime:  8").kind,
+            ParseErrorKind::DuplicateTimes
+        );
+        assert_eq!(
+            parse("Time:  7\nSpeed:  9").kind,
+            ParseErrorKind::UnknownRow("Speed".to_string())
+        );
+        assert_eq!(
+            parse("Time:  7 1a").kind,
+            ParseErrorKind::InvalidNumber("1a".to_string())
+        );
+        assert_eq!(
+            parse("Time:  7\nDistance:  -9").to_string(),
+            "line 2: `-9` is not a valid number"
+        );
+
+        // Each column fits in a u128, but not all of them together. The races are still
+        // valid, only the single race isn't.
+        let too_long = format!("Time:  7 1\nDistance:  {} 1", u128::MAX)
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        let sheet = RaceSheet::parse(&too_long).unwrap();
+        assert_eq!(
+            sheet.races().inner,
+            vec![Race::new(7, u128::MAX), Race::new(1, 1)]
+        );
+        let error = sheet.single_race().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::KernedOverflow(format!("{}1", u128::MAX))
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("line 2: `{}1` doesn't fit in a u128", u128::MAX)
+        );
+    }
+
+    #[test]
+    fn test_analysis() {
+        let analysis = RaceAnalysis::new(&Race::new(7, 9));
+        assert_eq!(analysis.best_hold, 3);
+        assert_eq!(analysis.count_wins(), 4);
+        assert_eq!(
+            analysis
+                .curve
+                .iter()
+                .map(|stats| stats.distance)
+                .collect::<Vec<u128>>(),
+            vec![0, 6, 10, 12, 12, 10, 6, 0]
+        );
+        assert_eq!(
+            analysis.curve[1],
+            HoldStats {
+                hold: 1,
+                distance: 6,
+                margin: -3,
+                wins: false
+            }
+        );
+        assert!(analysis.curve[2].wins);
+
+        // The distances and the record don't fit in an i128, but the holds still win.
+        let race =
+            Race::new(3, u128::MAX - 1).with_acceleration(Acceleration::Linear { rate: u128::MAX });
+        let analysis = RaceAnalysis::new(&race);
+        assert_eq!(analysis.count_wins() as u128, race.count_possible_wins());
+        assert_eq!(analysis.count_wins(), 2);
+        for race in [Race::new(7, 9), Race::new(30, 200), Race::new(4, 4)] {
+            let analysis = RaceAnalysis::new(&race);
+            assert_eq!(analysis.count_wins() as u128, race.count_possible_wins());
+        }
+    }
+
+    #[test]
+    fn test_analysis_export() {
+        let races = Races {
+            inner: vec![Race::new(3, 1), Race::new(2, 1)],
+        };
+        assert_eq!(
+            races.to_csv(),
+            r#"race,time,record,hold,distance,margin,wins
+1,3,1,0,0,-1,false
+1,3,1,1,2,1,true
+1,3,1,2,2,1,true
+1,3,1,3,0,-1,false
+2,2,1,0,0,-1,false
+2,2,1,1,1,0,false
+2,2,1,2,0,-1,false
+"#
+        );
+        assert_eq!(
+            races.to_table(),
+            r#"Race 1
+Time: 3 ms, record: 1 mm, best hold: 1 ms, ways to win: 2
+    hold     distance       margin win
+       0            0           -1 no
+       1            2            1 yes
+       2            2            1 yes
+       3            0           -1 no
+
+Race 2
+Time: 2 ms, record: 1 mm, best hold: 1 ms, ways to win: 0
+    hold     distance       margin win
+       0            0           -1 no
+       1            1            0 no
+       2            0           -1 no
+"#
+        );
+    }
 }
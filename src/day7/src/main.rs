@@ -1,118 +1,494 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 use std::path::PathBuf;
 use utils::read_lines;
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum HandType {
-    HighCard = 0,
-    Pair = 1,
-    TwoPairs = 2,
-    TreeOfAKind = 3,
-    FullHouse = 4,
-    FourOfAKind = 5,
-    FiveOfAKind = 6,
+    HighCard,
+    Pair,
+    TwoPairs,
+    TreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+    // Hands that don't have 5 cards can have other types, like six of a kind or two triples.
+    // These are defined by how many times each card appears, from the most to the least
+    // frequent card.
+    Other(Vec<usize>),
 }
 
-#[derive(Debug, Eq, PartialEq)]
-struct Hand {
-    hex: usize,
-    cards: HashMap<char, usize>,
-    bid: usize,
-    // Defines if the hand is played with `J` as a joker.
-    with_joker: bool,
-}
-
-impl PartialOrd<Self> for Hand {
+impl PartialOrd<Self> for HandType {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for Hand {
+// The stronger hand is the one with the most frequent card. If both have the same number
+// of the most frequent card, we look at the second most frequent card, and so on. For
+// hands of 5 cards this is the order of the puzzle.
+impl Ord for HandType {
     fn cmp(&self, other: &Self) -> Ordering {
-        let hand_type = self._type();
-        let other_hand_type = other._type();
-        if self._type() != other._type() {
-            hand_type.cmp(&other_hand_type)
-        } else {
-            self.hex.cmp(&other.hex)
+        self.counts().cmp(&other.counts())
+    }
+}
+
+impl HandType {
+    const ALL: [HandType; 7] = [
+        HandType::HighCard,
+        HandType::Pair,
+        HandType::TwoPairs,
+        HandType::TreeOfAKind,
+        HandType::FullHouse,
+        HandType::FourOfAKind,
+        HandType::FiveOfAKind,
+    ];
+
+    // Classifies a hand by how many times each card appears in it.
+    fn from_counts(mut counts: Vec<usize>) -> HandType {
+        counts.sort_by(|a, b| b.cmp(a));
+        HandType::ALL
+            .into_iter()
+            .find(|t| t.counts() == counts)
+            .unwrap_or(HandType::Other(counts))
+    }
+
+    // How many times each card appears in a hand of this type, from the most frequent.
+    fn counts(&self) -> Vec<usize> {
+        match self {
+            HandType::HighCard => vec![1, 1, 1, 1, 1],
+            HandType::Pair => vec![2, 1, 1, 1],
+            HandType::TwoPairs => vec![2, 2, 1],
+            HandType::TreeOfAKind => vec![3, 1, 1],
+            HandType::FullHouse => vec![3, 2],
+            HandType::FourOfAKind => vec![4, 1],
+            HandType::FiveOfAKind => vec![5],
+            HandType::Other(counts) => counts.clone(),
+        }
+    }
+
+    // The types that don't have a name are written as their counts, e.g. `3-3` for a hand
+    // with two triples.
+    fn name(&self) -> String {
+        match self {
+            HandType::HighCard => "high-card".to_string(),
+            HandType::Pair => "pair".to_string(),
+            HandType::TwoPairs => "two-pairs".to_string(),
+            HandType::TreeOfAKind => "three-of-a-kind".to_string(),
+            HandType::FullHouse => "full-house".to_string(),
+            HandType::FourOfAKind => "four-of-a-kind".to_string(),
+            HandType::FiveOfAKind => "five-of-a-kind".to_string(),
+            HandType::Other(counts) => counts
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join("-"),
         }
     }
 }
 
-impl Hand {
-    fn new(value: &str, with_joker: bool) -> Self {
-        let mut cards = HashMap::new();
-
-        let (hand, rank) = value.trim().split_once(' ').unwrap();
-        // To compare the cards in the order in which they appear we just convert the
-        // hand to a hex number and just compare numbers afterwards.
-        // 'T' -> 'A'
-        // 'J' => 'B' or '1' if we play with Joker
-        // 'Q' => 'C'
-        // 'K' => 'D'
-        // 'A' => 'E'
-        let j_replacement = if with_joker { '1' } else { 'B' };
-        let hex = hand
-            .replace('A', "E")
-            .replace('K', "D")
-            .replace('Q', "C")
-            .replace('J', &j_replacement.to_string())
-            .replace('T', "A");
-        for c in hex.chars() {
-            cards.entry(c).and_modify(|v| *v += 1).or_insert(1);
-        }
-        if with_joker {
-            if let Some(num_jokers) = cards.remove(&j_replacement) {
-                match cards.values().max() {
-                    Some(max) => {
-                        let best_card = cards
-                            .iter()
-                            .filter(|(_, v)| **v == *max)
-                            .max_by(|a, b| a.1.cmp(b.1))
-                            .unwrap();
-                        cards.entry(*best_card.0).and_modify(|v| *v += num_jokers);
-                    }
-                    None => {
-                        // It can be the case that we only had `J` in a hand. In this case
-                        // max will return None, and we have no more cards in our hands.
-                        // In this scenario we can just insert the highest possible card.
-                        cards.insert('A', 5);
-                    }
-                }
+impl TryFrom<&str> for HandType {
+    type Error = RuleSetError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if let Some(hand_type) = HandType::ALL.into_iter().find(|t| t.name() == value) {
+            return Ok(hand_type);
+        }
+        let counts = value
+            .split('-')
+            .map(|c| c.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| RuleSetError::UnknownHandType(value.to_string()))?;
+        if counts.contains(&0) {
+            return Err(RuleSetError::UnknownHandType(value.to_string()));
+        }
+        Ok(HandType::from_counts(counts))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RuleSetError {
+    // A line that is not one of `ranking:`, `wild:` or `ladder:`.
+    UnknownRule(String),
+    MissingRanking,
+    // The ranking has more than `MAX_CARDS` cards.
+    TooManyCards(usize),
+    // A card that appears more than once in the ranking.
+    DuplicateCard(char),
+    // A wild card that is not in the ranking.
+    UnknownWildCard(char),
+    UnknownHandType(String),
+    // A hand type that appears more than once in the ladder.
+    DuplicateHandType(String),
+}
+
+impl Display for RuleSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleSetError::UnknownRule(line) => write!(f, "unknown rule `{line}`"),
+            RuleSetError::MissingRanking => write!(f, "the card ranking is missing"),
+            RuleSetError::TooManyCards(count) => write!(
+                f,
+                "the ranking has {count} cards, but there can be at most {MAX_CARDS}"
+            ),
+            RuleSetError::DuplicateCard(c) => write!(f, "`{c}` appears twice in the ranking"),
+            RuleSetError::UnknownWildCard(c) => write!(f, "wild card `{c}` is not in the ranking"),
+            RuleSetError::UnknownHandType(name) => write!(f, "unknown hand type `{name}`"),
+            RuleSetError::DuplicateHandType(name) => {
+                write!(f, "`{name}` appears twice in the ladder")
             }
         }
-        Hand {
-            cards,
-            bid: rank.parse::<usize>().unwrap(),
-            hex: usize::from_str_radix(hex.as_str(), 16).unwrap(),
-            with_joker,
+    }
+}
+
+// What is wrong with a hand.
+#[derive(Debug, PartialEq)]
+enum ParseErrorKind {
+    // The cards must be followed by the bid, separated by a space.
+    MissingBid,
+    InvalidBid(String),
+    EmptyHand,
+    // A card that is not in the ranking of the rules.
+    UnknownCard(char),
+    // There are too many ways to assign this many wild cards to try them all, see
+    // `MAX_WILD_CARD_CHOICES`.
+    TooManyWildCards(usize),
+}
+
+type ParseError = utils::ParseError<ParseErrorKind>;
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::MissingBid => write!(f, "the hand has no bid"),
+            ParseErrorKind::InvalidBid(bid) => write!(f, "`{bid}` is not a valid bid"),
+            ParseErrorKind::EmptyHand => write!(f, "the hand has no cards"),
+            ParseErrorKind::UnknownCard(c) => write!(f, "`{c}` is not in the ranking"),
+            ParseErrorKind::TooManyWildCards(count) => {
+                write!(f, "{count} wild cards are too many to try all their values")
+            }
+        }
+    }
+}
+
+// The rules by which hands are ranked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RuleSet {
+    // The cards, from the weakest to the strongest.
+    ranking: Vec<char>,
+    // The cards that act like whatever card makes the strongest hand type.
+    wild: Vec<char>,
+    // Reorders hand types, from the weakest to the strongest. The types in the ladder swap
+    // places among themselves, while the others keep their natural place. An empty ladder
+    // keeps the natural order of the hand types.
+    ladder: Vec<HandType>,
+}
+
+impl RuleSet {
+    fn part1() -> Self {
+        RuleSet {
+            ranking: "23456789TJQKA".chars().collect(),
+            wild: vec![],
+            ladder: vec![],
         }
     }
 
-    fn _type(&self) -> HandType {
-        let vals = self.cards.values().copied().collect::<Vec<usize>>();
-        match vals.len() {
-            1 => HandType::FiveOfAKind, // a single value means 5 of a kind
-            2 => {
-                if vals.contains(&4) {
-                    HandType::FourOfAKind
-                } else {
-                    HandType::FullHouse
+    // `J` is a joker, which makes it the weakest card.
+    fn part2() -> Self {
+        RuleSet {
+            ranking: "J23456789TQKA".chars().collect(),
+            wild: vec!['J'],
+            ladder: vec![],
+        }
+    }
+
+    // Parses rules written as:
+    // ranking: J23456789TQKA
+    // wild: J
+    // ladder: high-card pair two-pairs three-of-a-kind full-house four-of-a-kind five-of-a-kind
+    // Only the ranking is mandatory. By default there are no wild cards, and the hand types
+    // are in their natural order, which is the one from the puzzle.
+    fn parse(lines: &[String]) -> Result<Self, RuleSetError> {
+        let mut rules = RuleSet {
+            ranking: vec![],
+            wild: vec![],
+            ladder: vec![],
+        };
+        for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (rule, value) = line
+                .split_once(':')
+                .ok_or_else(|| RuleSetError::UnknownRule(line.to_string()))?;
+            let cards = value.chars().filter(|c| !c.is_whitespace());
+            match rule.trim() {
+                "ranking" => rules.ranking = cards.collect(),
+                "wild" => rules.wild = cards.collect(),
+                "ladder" => {
+                    rules.ladder = value
+                        .split_whitespace()
+                        .map(HandType::try_from)
+                        .collect::<Result<Vec<HandType>, RuleSetError>>()?
                 }
+                _ => return Err(RuleSetError::UnknownRule(line.to_string())),
+            }
+        }
+
+        if rules.ranking.is_empty() {
+            return Err(RuleSetError::MissingRanking);
+        }
+        if rules.ranking.len() > MAX_CARDS {
+            return Err(RuleSetError::TooManyCards(rules.ranking.len()));
+        }
+        for (i, c) in rules.ranking.iter().enumerate() {
+            if rules.ranking[i + 1..].contains(c) {
+                return Err(RuleSetError::DuplicateCard(*c));
+            }
+        }
+        if let Some(c) = rules.wild.iter().find(|c| !rules.ranking.contains(c)) {
+            return Err(RuleSetError::UnknownWildCard(*c));
+        }
+        for (i, hand_type) in rules.ladder.iter().enumerate() {
+            if rules.ladder[i + 1..].contains(hand_type) {
+                return Err(RuleSetError::DuplicateHandType(hand_type.name()));
+            }
+        }
+        Ok(rules)
+    }
+
+    fn card_rank(&self, card: char) -> Option<usize> {
+        self.ranking.iter().position(|c| *c == card)
+    }
+
+    // The ranks of the cards of a hand, and how many times each rank appears in it. Fails
+    // if the wild cards of the hand can't be resolved in a reasonable time.
+    fn count_cards(&self, hand: &str) -> Result<(Vec<usize>, [usize; MAX_CARDS]), ParseErrorKind> {
+        if hand.is_empty() {
+            return Err(ParseErrorKind::EmptyHand);
+        }
+        let ranks = hand
+            .chars()
+            .map(|c| self.card_rank(c).ok_or(ParseErrorKind::UnknownCard(c)))
+            .collect::<Result<Vec<usize>, ParseErrorKind>>()?;
+        let mut counts = [0; MAX_CARDS];
+        for rank in ranks.iter() {
+            counts[*rank] += 1;
+        }
+        let num_wild = hand.chars().filter(|c| self.is_wild(*c)).count();
+        if !self.ladder.is_empty() && self.wild_card_choices(num_wild) > MAX_WILD_CARD_CHOICES {
+            return Err(ParseErrorKind::TooManyWildCards(num_wild));
+        }
+        Ok((ranks, counts))
+    }
+
+    // The number of multisets of `num_wild` cards that are not wild, which is how many ways
+    // of assigning the wild cards `search_wild_cards` tries. Saturates at usize::MAX.
+    fn wild_card_choices(&self, num_wild: usize) -> usize {
+        let cards = self.ranking.iter().filter(|c| !self.is_wild(**c)).count();
+        // C(cards + num_wild - 1, num_wild), where each partial product is itself a binomial
+        // coefficient, so the divisions are exact.
+        (1..=num_wild)
+            .try_fold(1usize, |choices, i| {
+                Some(choices.checked_mul(cards + i - 1)? / i)
+            })
+            .unwrap_or(usize::MAX)
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        self.wild.contains(&card)
+    }
+
+    // Returns the hand that the wild cards of `hand` turn into to make it as strong as
+    // possible, e.g. `KTTTT` for `KTJJT` with the rules of part 2.
+    fn best_hand(&self, hand: &str) -> Result<String, ParseErrorKind> {
+        let (ranks, mut counts) = self.count_cards(hand)?;
+        Ok(self.resolve_wild_cards(&ranks, &mut counts))
+    }
+
+    // Replaces the wild cards of a hand, given by its ranks and how many times each rank
+    // appears in it. The counts are updated with the cards the wild cards stand for, and
+    // the resolved hand is returned.
+    fn resolve_wild_cards(&self, ranks: &[usize], counts: &mut [usize; MAX_CARDS]) -> String {
+        let mut num_wild = 0;
+        for (rank, card) in self.ranking.iter().enumerate() {
+            if self.is_wild(*card) {
+                num_wild += counts[rank];
+                counts[rank] = 0;
             }
-            3 => {
-                if vals.contains(&3) {
-                    HandType::TreeOfAKind
+        }
+        let mut targets = self.wild_card_targets(num_wild, counts).into_iter();
+        ranks
+            .iter()
+            .map(|rank| {
+                let card = self.ranking[*rank];
+                if self.is_wild(card) {
+                    self.ranking[targets.next().unwrap()]
                 } else {
-                    HandType::TwoPairs
+                    card
                 }
+            })
+            .collect()
+    }
+
+    // Chooses the ranks that `num_wild` wild cards stand for, given the counts of the
+    // other cards of the hand, and adds them to the counts.
+    //
+    // With the natural order of the hand types, the strongest type is always obtained by
+    // adding all the wild cards to the most frequent card: this makes the first count as
+    // high as possible, and hand types are compared by their counts from the first one.
+    // A ladder can rank a type with a lower first count higher, so in that case we try
+    // every way of assigning the wild cards.
+    fn wild_card_targets(&self, num_wild: usize, counts: &mut [usize; MAX_CARDS]) -> Vec<usize> {
+        if num_wild == 0 {
+            return vec![];
+        }
+        let cards = (0..self.ranking.len())
+            .filter(|r| !self.is_wild(self.ranking[*r]))
+            .collect::<Vec<usize>>();
+        // If all the cards are wild, they can't stand for anything else.
+        let Some(most_frequent) = cards.iter().copied().max_by_key(|r| (counts[*r], *r)) else {
+            counts[0] += num_wild;
+            return vec![0; num_wild];
+        };
+
+        let mut targets = vec![most_frequent; num_wild];
+        if !self.ladder.is_empty() {
+            let mut best = self.strength_with(counts, &targets);
+            let mut current = vec![];
+            self.search_wild_cards(
+                &cards,
+                num_wild,
+                counts,
+                &mut current,
+                &mut best,
+                &mut targets,
+            );
+        }
+        for target in targets.iter() {
+            counts[*target] += 1;
+        }
+        targets
+    }
+
+    // Tries every multiset of `num_wild` cards, keeping the strongest one in `targets`. The
+    // cards are picked in increasing order so that every multiset is only tried once. There
+    // are `wild_card_choices(num_wild)` of them, which grows quickly with the number of wild
+    // cards, so hands are limited to `MAX_WILD_CARD_CHOICES` when they are parsed.
+    fn search_wild_cards(
+        &self,
+        cards: &[usize],
+        num_wild: usize,
+        counts: &[usize; MAX_CARDS],
+        current: &mut Vec<usize>,
+        best: &mut Vec<usize>,
+        targets: &mut Vec<usize>,
+    ) {
+        if current.len() == num_wild {
+            let strength = self.strength_with(counts, current);
+            if strength > *best {
+                *best = strength;
+                targets.clone_from(current);
             }
-            4 => HandType::Pair,
-            5 => HandType::HighCard,
-            _ => panic!("Invalid number of cards"),
+            return;
         }
+        for (i, card) in cards.iter().enumerate() {
+            current.push(*card);
+            self.search_wild_cards(&cards[i..], num_wild, counts, current, best, targets);
+            current.pop();
+        }
+    }
+
+    // The strength of a hand with the given counts once `targets` are added to it.
+    fn strength_with(&self, counts: &[usize; MAX_CARDS], targets: &[usize]) -> Vec<usize> {
+        let mut counts = *counts;
+        for target in targets.iter() {
+            counts[*target] += 1;
+        }
+        let hand_type = HandType::from_counts(counts.into_iter().filter(|c| *c > 0).collect());
+        self.ladder_rank(&hand_type).counts()
+    }
+
+    // Returns the type in whose place `hand_type` is ranked. The types in the ladder take
+    // the places they occupy in the natural order, but in the order of the ladder.
+    fn ladder_rank(&self, hand_type: &HandType) -> HandType {
+        match self.ladder.iter().position(|t| t == hand_type) {
+            Some(position) => {
+                let mut places = self.ladder.clone();
+                places.sort();
+                places.swap_remove(position)
+            }
+            None => hand_type.clone(),
+        }
+    }
+}
+
+// The maximum number of different cards in a ranking, so that we can count the cards of a
+// hand in a fixed-size array.
+const MAX_CARDS: usize = 64;
+
+// With a ladder, every way of assigning the wild cards of a hand is tried. This is enough
+// for 8 wild cards with the 12 other cards of part 2, but not for 9 of them.
+const MAX_WILD_CARD_CHOICES: usize = 100_000;
+
+#[derive(Debug, Eq, PartialEq)]
+struct Hand {
+    cards: String,
+    // The rank of each card, in the order in which they are in the hand. Comparing these
+    // breaks the ties between hands of the same type.
+    ranks: Vec<usize>,
+    // The hand with the wild cards replaced by the cards they stand for.
+    resolved: String,
+    // The type is computed once, when the hand is created.
+    hand_type: HandType,
+    // The counts of the type in whose place the hand is ranked by the ladder of the rules.
+    // Together with `ranks`, this is what hands are sorted by.
+    strength: Vec<usize>,
+    bid: usize,
+}
+
+impl PartialOrd<Self> for Hand {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.strength
+            .cmp(&other.strength)
+            .then_with(|| self.ranks.cmp(&other.ranks))
+    }
+}
+
+impl Hand {
+    // Parses a hand and its bid, e.g. `KTJJT 220`. The cards must all be in the ranking of
+    // the rules.
+    fn parse(value: &str, rules: &RuleSet) -> Result<Self, ParseErrorKind> {
+        let (hand, bid) = value
+            .trim()
+            .split_once(' ')
+            .ok_or(ParseErrorKind::MissingBid)?;
+        let bid = bid.trim();
+        let bid = bid
+            .parse::<usize>()
+            .map_err(|_| ParseErrorKind::InvalidBid(bid.to_string()))?;
+        let (ranks, mut counts) = rules.count_cards(hand)?;
+        let resolved = rules.resolve_wild_cards(&ranks, &mut counts);
+
+        let hand_type = HandType::from_counts(counts.into_iter().filter(|c| *c > 0).collect());
+        let strength = rules.ladder_rank(&hand_type).counts();
+        Ok(Hand {
+            cards: hand.to_string(),
+            ranks,
+            resolved,
+            hand_type,
+            strength,
+            bid,
+        })
+    }
+
+    fn _type(&self) -> HandType {
+        self.hand_type.clone()
     }
 }
 
@@ -122,13 +498,21 @@ struct Hands {
 }
 
 impl Hands {
-    fn new(input: &[String], with_joker: bool) -> Self {
+    fn parse(input: &[String], rules: &RuleSet) -> Result<Self, ParseError> {
         let mut inner = input
             .iter()
-            .map(|s| Hand::new(s.as_str(), with_joker))
-            .collect::<Vec<Hand>>();
+            .enumerate()
+            .map(|(index, s)| {
+                Hand::parse(s.as_str(), rules).map_err(|kind| ParseError {
+                    line: index + 1,
+                    kind,
+                })
+            })
+            .collect::<Result<Vec<Hand>, ParseError>>()?;
+        // The hands are compared by their cached strength and ranks. The sort is stable, so
+        // tied hands keep the order of the input.
         inner.sort_by(|a, b| b.cmp(a));
-        Self { inner }
+        Ok(Self { inner })
     }
 
     fn total_winnings(&self) -> usize {
@@ -139,62 +523,214 @@ impl Hands {
             .map(|(rank, hand)| (len - rank) * hand.bid)
             .sum()
     }
+
+    // Lists the hands from the strongest to the weakest, with what each one adds to the
+    // total winnings.
+    fn report(&self) -> Vec<RankedHand> {
+        let len = self.inner.len();
+        self.inner
+            .iter()
+            .enumerate()
+            .map(|(index, hand)| RankedHand {
+                cards: hand.cards.clone(),
+                resolved: hand.resolved.clone(),
+                hand_type: hand.hand_type.clone(),
+                rank: len - index,
+                bid: hand.bid,
+                winnings: (len - index) * hand.bid,
+            })
+            .collect()
+    }
+
+    // Finds the hands that appear more than once. Identical hands are equal, so the order in
+    // which they are ranked is arbitrary, and if their bids differ so is the total.
+    fn ties(&self) -> Vec<Tie> {
+        let len = self.inner.len();
+        let mut ties = vec![];
+        let mut start = 0;
+        for group in self.inner.chunk_by(|a, b| a.cmp(b) == Ordering::Equal) {
+            if group.len() > 1 {
+                ties.push(Tie {
+                    cards: group[0].cards.clone(),
+                    ranks: len - start - group.len() + 1..len - start + 1,
+                    bids: group.iter().map(|h| h.bid).collect(),
+                });
+            }
+            start += group.len();
+        }
+        ties
+    }
+
+    fn to_report(&self) -> String {
+        let mut report = format!(
+            "{:>6} {:<8} {:<8} {:<16} {:>6} {:>12}\n",
+            "rank", "hand", "resolved", "type", "bid", "winnings"
+        );
+        for line in self.report() {
+            report.push_str(&format!("{line}\n"));
+        }
+        for tie in self.ties() {
+            report.push_str(&format!("{tie}\n"));
+        }
+        report
+    }
+}
+
+// A hand as it is ranked, with the winnings it gets.
+#[derive(Debug, PartialEq)]
+struct RankedHand {
+    cards: String,
+    resolved: String,
+    hand_type: HandType,
+    rank: usize,
+    bid: usize,
+    winnings: usize,
+}
+
+impl Display for RankedHand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>6} {:<8} {:<8} {:<16} {:>6} {:>12}",
+            self.rank,
+            self.cards,
+            self.resolved,
+            self.hand_type.name(),
+            self.bid,
+            self.winnings
+        )
+    }
+}
+
+// Identical hands, which share the ranks in `ranks`.
+#[derive(Debug, PartialEq)]
+struct Tie {
+    cards: String,
+    ranks: Range<usize>,
+    bids: Vec<usize>,
+}
+
+impl Display for Tie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tie: {} appears {} times, ranks {} to {}, bids {:?}",
+            self.cards,
+            self.bids.len(),
+            self.ranks.start,
+            self.ranks.end - 1,
+            self.bids
+        )
+    }
 }
 
 fn main() {
     let path = PathBuf::from("src/day7/src/input.txt");
     let input = read_lines(path);
-    let hands = Hands::new(&input, false);
+    // The ranking of part 2 can be printed with `--report`.
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+
+    let rules = RuleSet::part1();
+    let hands = match Hands::parse(&input, &rules) {
+        Ok(hands) => hands,
+        Err(e) => {
+            eprintln!("Invalid hands: {e}");
+            return;
+        }
+    };
     println!("Part 1: {}", hands.total_winnings()); // 249748283
-    let hands = Hands::new(&input, true);
+
+    // Both rule sets have the same cards, so the hands are valid for part 2 too.
+    let rules = RuleSet::part2();
+    let hands = match Hands::parse(&input, &rules) {
+        Ok(hands) => hands,
+        Err(e) => {
+            eprintln!("Invalid hands: {e}");
+            return;
+        }
+    };
     println!("Part 2: {}", hands.total_winnings()); // 248029057
+    if flags.iter().any(|flag| flag == "--report") {
+        print!("{}", hands.to_report());
+    }
+
+    // The hands can also be ranked with custom rules loaded from a file.
+    if let Some(path) = args.first() {
+        match RuleSet::parse(&read_lines(PathBuf::from(path))) {
+            Ok(rules) => {
+                match Hands::parse(&input, &rules) {
+                    Ok(hands) => println!("Custom rules: {}", hands.total_winnings()),
+                    Err(e) => eprintln!("Invalid hands for these rules: {e}"),
+                }
+                // Any other argument is a hand whose wild cards we want to see resolved.
+                for hand in args.iter().skip(1) {
+                    match rules.best_hand(hand) {
+                        Ok(best) => println!("{hand} -> {best}"),
+                        Err(e) => eprintln!("{hand}: {e}"),
+                    }
+                }
+            }
+            Err(e) => eprintln!("Invalid rules: {e}"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Hand, HandType, Hands};
+    use crate::{
+        Hand, HandType, Hands, ParseError, ParseErrorKind, RankedHand, RuleSet, RuleSetError, Tie,
+        MAX_CARDS,
+    };
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    fn parse_hand(value: &str, rules: &RuleSet) -> Hand {
+        Hand::parse(value, rules).unwrap()
+    }
 
     #[test]
     fn test_values_comparison() {
-        let full_a = Hand::new("AAAAA 123", false);
-        let full_j = Hand::new("JJJJJ 123", false);
+        let rules = RuleSet::part1();
+        let full_a = parse_hand("AAAAA 123", &rules);
+        let full_j = parse_hand("JJJJJ 123", &rules);
         assert!(full_a > full_j);
 
-        let h1 = Hand::new("22222 1", false);
-        let h2 = Hand::new("AAAAK 1", false);
+        let h1 = parse_hand("22222 1", &rules);
+        let h2 = parse_hand("AAAAK 1", &rules);
         assert_eq!(h1._type(), HandType::FiveOfAKind);
         assert_eq!(h2._type(), HandType::FourOfAKind);
         assert!(h1 > h2);
 
-        let h1 = Hand::new("22345 1", false);
+        let h1 = parse_hand("22345 1", &rules);
         assert_eq!(h1._type(), HandType::Pair);
-        let h2 = Hand::new("AKQJT 1", false);
+        let h2 = parse_hand("AKQJT 1", &rules);
 
         assert_eq!(h2._type(), HandType::HighCard);
         assert!(h1 > h2);
 
-        let h1 = Hand::new("A224A 1", false);
+        let h1 = parse_hand("A224A 1", &rules);
         assert_eq!(h1._type(), HandType::TwoPairs);
-        let h2 = Hand::new("KKQQJ 1", false);
+        let h2 = parse_hand("KKQQJ 1", &rules);
         assert_eq!(h2._type(), HandType::TwoPairs);
         assert!(h1 > h2);
 
-        let h1 = Hand::new("22223 1", false);
-        let h2 = Hand::new("AAAKK 1", false);
+        let h1 = parse_hand("22223 1", &rules);
+        let h2 = parse_hand("AAAKK 1", &rules);
         assert!(h1 > h2);
 
-        let h1 = Hand::new("22333 1", false);
-        let h2 = Hand::new("AA222 1", false);
+        let h1 = parse_hand("22333 1", &rules);
+        let h2 = parse_hand("AA222 1", &rules);
         assert!(h2 > h1);
 
-        let h1 = Hand::new("77888 11", false);
+        let h1 = parse_hand("77888 11", &rules);
         assert_eq!(h1._type(), HandType::FullHouse);
-        let h2 = Hand::new("77788 2", false);
+        let h2 = parse_hand("77788 2", &rules);
         assert_eq!(h2._type(), HandType::FullHouse);
         assert!(h1 > h2);
 
-        let h1 = Hand::new("33332 1", false);
-        let h2 = Hand::new("2AAAA 2", false);
+        let h1 = parse_hand("33332 1", &rules);
+        let h2 = parse_hand("2AAAA 2", &rules);
         assert!(h1 > h2);
     }
 
@@ -219,7 +755,8 @@ QQQJA 483
         .lines()
         .map(String::from)
         .collect::<Vec<String>>();
-        let hands = Hands::new(input, false);
+        let rules = RuleSet::part1();
+        let hands = Hands::parse(&input, &rules).unwrap();
         assert_eq!(hands.total_winnings(), 6440);
     }
 
@@ -234,7 +771,422 @@ QQQJA 483
         .lines()
         .map(String::from)
         .collect::<Vec<String>>();
-        let hands = Hands::new(input, true);
+        let rules = RuleSet::part2();
+        let hands = Hands::parse(&input, &rules).unwrap();
         assert_eq!(hands.total_winnings(), 5905);
     }
+
+    #[test]
+    fn test_wild_cards() {
+        let rules = RuleSet::part2();
+        assert_eq!(parse_hand("KTJJT 1", &rules)._type(), HandType::FourOfAKind);
+        assert_eq!(parse_hand("JJJJJ 1", &rules)._type(), HandType::FiveOfAKind);
+        // The joker is the weakest card.
+        assert!(parse_hand("JKKK2 1", &rules) < parse_hand("QQQQ2 1", &rules));
+        assert!(parse_hand("JJJJJ 1", &rules) < parse_hand("22222 1", &rules));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let rules = RuleSet::part1();
+        assert_eq!(
+            Hand::parse("KTXJT 1", &rules),
+            Err(ParseErrorKind::UnknownCard('X'))
+        );
+        assert_eq!(
+            Hand::parse("KTJJT", &rules),
+            Err(ParseErrorKind::MissingBid)
+        );
+        assert_eq!(
+            Hand::parse("KTJJT x", &rules),
+            Err(ParseErrorKind::InvalidBid("x".to_string()))
+        );
+        // The card is valid with other rules.
+        let rules = RuleSet::parse(&["ranking: 23456789TJQKA1".to_string()]).unwrap();
+        assert!(Hand::parse("KT1JT 1", &rules).is_ok());
+        let error = Hands::parse(
+            &["KTJJT 1".to_string(), "KT1JT 1".to_string()],
+            &RuleSet::part1(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::UnknownCard('1')
+            }
+        );
+        assert_eq!(error.to_string(), "line 2: `1` is not in the ranking");
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        let rules = RuleSet::parse(&lines(
+            r#"ranking: 2 3 4 5 6 7 8 9 T J Q K A
+wild: 2
+ladder: high-card pair two-pairs full-house three-of-a-kind four-of-a-kind five-of-a-kind"#,
+        ))
+        .unwrap();
+        assert_eq!(rules.wild, vec!['2']);
+        // A three of a kind now beats a full house.
+        assert!(parse_hand("33345 1", &rules) > parse_hand("KKKQQ 1", &rules));
+        // Wild cards keep their rank for ties.
+        assert!(parse_hand("2AAAK 1", &rules) < parse_hand("3AAAA 1", &rules));
+        assert_eq!(parse_hand("22222 1", &rules)._type(), HandType::FiveOfAKind);
+
+        assert_eq!(
+            RuleSet::parse(&lines("ranking: J23456789TQKA\nwild: J")).unwrap(),
+            RuleSet::part2()
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("wild: J")),
+            Err(RuleSetError::MissingRanking)
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("ranking: AKA")),
+            Err(RuleSetError::DuplicateCard('A'))
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("ranking: AK\nwild: J")),
+            Err(RuleSetError::UnknownWildCard('J'))
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("ranking: AK\nladder: pair 3-3 2-1-1-1")),
+            Err(RuleSetError::DuplicateHandType("pair".to_string()))
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("ranking: AK\nladder: flush")),
+            Err(RuleSetError::UnknownHandType("flush".to_string()))
+        );
+        assert_eq!(
+            RuleSet::parse(&lines("suits: 4")),
+            Err(RuleSetError::UnknownRule("suits: 4".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_hand_sizes() {
+        let rules = RuleSet::part1();
+        assert_eq!(parse_hand("AA 1", &rules)._type(), HandType::Other(vec![2]));
+        assert_eq!(parse_hand("A 1", &rules)._type(), HandType::Other(vec![1]));
+        assert_eq!(
+            parse_hand("AAAAAA 1", &rules)._type(),
+            HandType::Other(vec![6])
+        );
+        assert_eq!(
+            parse_hand("AAAKKK 1", &rules)._type(),
+            HandType::Other(vec![3, 3])
+        );
+        assert_eq!(HandType::Other(vec![3, 3]).name(), "3-3");
+        // Two triples beat a full house with an extra card, but not four of a kind.
+        assert!(parse_hand("222333 1", &rules) > parse_hand("AAAKK2 1", &rules));
+        assert!(parse_hand("222333 1", &rules) < parse_hand("22223K 1", &rules));
+
+        // More than 16 cards, ties are broken card by card.
+        let long = "23456789TJQKA23456789";
+        let h1 = parse_hand(&format!("{long}A 1"), &rules);
+        let h2 = parse_hand(&format!("{long}K 1"), &rules);
+        assert_eq!(h1._type(), h2._type());
+        assert!(h1 > h2);
+
+        let rules = RuleSet::part2();
+        assert_eq!(
+            parse_hand("JJJJJJJ 1", &rules)._type(),
+            HandType::Other(vec![7])
+        );
+        assert_eq!(
+            parse_hand("KKQQJ2 1", &rules)._type(),
+            HandType::Other(vec![3, 2, 1])
+        );
+    }
+
+    #[test]
+    fn test_ladder() {
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        // Only swaps pairs and two pairs, the rest keep their place.
+        let rules =
+            RuleSet::parse(&lines("ranking: 23456789TJQKA\nladder: two-pairs pair")).unwrap();
+        assert!(parse_hand("22345 1", &rules) > parse_hand("22335 1", &rules));
+        assert!(parse_hand("22335 1", &rules) > parse_hand("23456 1", &rules));
+        assert!(parse_hand("22235 1", &rules) > parse_hand("22345 1", &rules));
+        assert_eq!(HandType::try_from("3-2"), Ok(HandType::FullHouse));
+        assert_eq!(HandType::try_from("6"), Ok(HandType::Other(vec![6])));
+        assert_eq!(
+            HandType::try_from("3-0"),
+            Err(RuleSetError::UnknownHandType("3-0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cached_strength() {
+        let rules = RuleSet::part2();
+        let hand = parse_hand("KTJJT 220", &rules);
+        assert_eq!(hand.hand_type, HandType::FourOfAKind);
+        assert_eq!(hand.strength, vec![4, 1]);
+        assert_eq!(hand.ranks, vec![11, 9, 0, 0, 9]);
+
+        // The strength follows the ladder, the type doesn't.
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        let rules =
+            RuleSet::parse(&lines("ranking: 23456789TJQKA\nladder: two-pairs pair")).unwrap();
+        let hand = parse_hand("22345 1", &rules);
+        assert_eq!(hand.hand_type, HandType::Pair);
+        assert_eq!(hand.strength, vec![2, 2, 1]);
+
+        let ranking = (0..65).map(|_| 'X').collect::<String>();
+        assert_eq!(
+            RuleSet::parse(&lines(&format!("ranking: {ranking}"))),
+            Err(RuleSetError::TooManyCards(65))
+        );
+    }
+
+    // Generates hands of 5 cards with a simple linear congruential generator.
+    fn random_hands(count: usize) -> Vec<String> {
+        let cards = "23456789TJQKA".chars().collect::<Vec<char>>();
+        let mut seed: u64 = 7;
+        (0..count)
+            .map(|bid| {
+                let hand = (0..5)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        cards[(seed >> 33) as usize % cards.len()]
+                    })
+                    .collect::<String>();
+                format!("{hand} {bid}")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_many_hands() {
+        // The cached strength sorts the hands like their types and cards computed again.
+        let input = random_hands(1000);
+        for rules in [RuleSet::part1(), RuleSet::part2()] {
+            let hands = Hands::parse(&input, &rules).unwrap();
+            assert_eq!(hands.inner.len(), 1000);
+            let keys = hands
+                .inner
+                .iter()
+                .map(|hand| {
+                    let resolved = rules.best_hand(&hand.cards).unwrap();
+                    let ranks = hand
+                        .cards
+                        .chars()
+                        .map(|c| rules.card_rank(c).unwrap())
+                        .collect::<Vec<usize>>();
+                    (
+                        strength(&rules, &resolved.chars().collect::<Vec<char>>()),
+                        ranks,
+                    )
+                })
+                .collect::<Vec<(Vec<usize>, Vec<usize>)>>();
+            assert!(keys.windows(2).all(|w| w[0] >= w[1]));
+        }
+    }
+
+    // Run with `cargo test -p day7 -- --ignored` to check that sorting many hands is fast.
+    #[test]
+    #[ignore]
+    fn bench_many_hands() {
+        let input = random_hands(100_000);
+        for rules in [RuleSet::part1(), RuleSet::part2()] {
+            let start = Instant::now();
+            let hands = Hands::parse(&input, &rules).unwrap();
+            let elapsed = start.elapsed();
+            println!("{} hands in {elapsed:?}", hands.inner.len());
+            assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+        }
+    }
+
+    // Finds the strongest type a hand can have by trying every card for every wild card.
+    // The type doesn't depend on the order of the cards, so the wild cards are replaced by
+    // cards in increasing order.
+    fn brute_force_type(rules: &RuleSet, hand: &[char], cards: &[char]) -> Vec<usize> {
+        match hand.iter().position(|c| rules.is_wild(*c)) {
+            None => strength(rules, hand),
+            Some(i) => (0..cards.len())
+                .map(|k| {
+                    let mut hand = hand.to_vec();
+                    hand[i] = cards[k];
+                    brute_force_type(rules, &hand, &cards[k..])
+                })
+                .max()
+                .unwrap(),
+        }
+    }
+
+    // The strength of a hand without wild cards.
+    fn strength(rules: &RuleSet, hand: &[char]) -> Vec<usize> {
+        let mut counts = [0; MAX_CARDS];
+        for c in hand.iter() {
+            counts[rules.card_rank(*c).unwrap()] += 1;
+        }
+        rules.strength_with(&counts, &[])
+    }
+
+    // Checks that `resolved` only replaces the wild cards of `hand`, and that it is as
+    // strong as the strongest hand found by brute force.
+    fn check_best_hand(
+        rules: &RuleSet,
+        hand: &[char],
+        brute_force: &mut HashMap<Vec<char>, Vec<usize>>,
+    ) {
+        let resolved = rules.best_hand(&hand.iter().collect::<String>()).unwrap();
+        let resolved = resolved.chars().collect::<Vec<char>>();
+        assert_eq!(resolved.len(), hand.len());
+        for (c, r) in hand.iter().zip(resolved.iter()) {
+            assert!(!rules.is_wild(*r));
+            if !rules.is_wild(*c) {
+                assert_eq!(c, r);
+            }
+        }
+        // The type doesn't depend on the order of the cards.
+        let mut sorted = hand.to_vec();
+        sorted.sort();
+        let expected = brute_force.entry(sorted).or_insert_with_key(|sorted| {
+            let cards = rules
+                .ranking
+                .iter()
+                .copied()
+                .filter(|c| !rules.is_wild(*c))
+                .collect::<Vec<char>>();
+            brute_force_type(rules, sorted, &cards)
+        });
+        assert_eq!(&strength(rules, &resolved), expected);
+    }
+
+    #[test]
+    fn test_best_hand() {
+        let rules = RuleSet::part2();
+        assert_eq!(rules.best_hand("KTJJT").unwrap(), "KTTTT");
+        assert_eq!(rules.best_hand("T55J5").unwrap(), "T5555");
+        assert_eq!(rules.best_hand("QQQJA").unwrap(), "QQQQA");
+        assert_eq!(rules.best_hand("JJJJJ").unwrap(), "AAAAA");
+        assert_eq!(rules.best_hand("32T3K").unwrap(), "32T3K");
+        assert_eq!(parse_hand("KTJJT 220", &rules).resolved, "KTTTT");
+
+        // Every 5-card hand.
+        let cards = rules.ranking.clone();
+        let mut brute_force = HashMap::new();
+        for i in 0..cards.len().pow(5) {
+            let hand = (0..5)
+                .map(|k| cards[i / cards.len().pow(k) % cards.len()])
+                .collect::<Vec<char>>();
+            check_best_hand(&rules, &hand, &mut brute_force);
+        }
+    }
+
+    #[test]
+    fn test_best_hand_with_ladder() {
+        // Two pairs are ranked in the place of four of a kind, so the joker should make a
+        // second pair rather than a triple.
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        let rules = RuleSet::parse(&lines(
+            "ranking: J23456789TQKA\nwild: J\nladder: four-of-a-kind two-pairs",
+        ))
+        .unwrap();
+        assert_eq!(rules.best_hand("22J34").unwrap(), "22334");
+        assert_eq!(rules.best_hand("2JJ34").unwrap(), "22334");
+        assert_eq!(
+            rules.best_hand("2XJ34"),
+            Err(ParseErrorKind::UnknownCard('X'))
+        );
+        assert_eq!(rules.best_hand(""), Err(ParseErrorKind::EmptyHand));
+        // Every way of assigning the jokers is tried, so there can't be too many of them.
+        assert_eq!(rules.wild_card_choices(2), 78);
+        assert_eq!(rules.wild_card_choices(8), 75582);
+        assert_eq!(rules.best_hand("JJJJJJJJ").unwrap(), "AAAAAAAA");
+        assert_eq!(
+            rules.best_hand("JJJJJJJJJ"),
+            Err(ParseErrorKind::TooManyWildCards(9))
+        );
+        assert_eq!(rules.wild_card_choices(1000), usize::MAX);
+        // Without a ladder the wild cards are all given to the most frequent card.
+        assert_eq!(
+            RuleSet::part2().best_hand("JJJJJJJJJ").unwrap(),
+            "AAAAAAAAA"
+        );
+
+        // Every 5-card hand, up to the order of the cards.
+        let cards = rules.ranking.clone();
+        let mut brute_force = HashMap::new();
+        let mut hand = [0; 5];
+        loop {
+            check_best_hand(
+                &rules,
+                &hand.iter().map(|i| cards[*i]).collect::<Vec<char>>(),
+                &mut brute_force,
+            );
+            // Next non-decreasing sequence of indices.
+            let Some(k) = (0..5).rev().find(|k| hand[*k] + 1 < cards.len()) else {
+                break;
+            };
+            let next = hand[k] + 1;
+            hand[k..].fill(next);
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let input = r#"32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483
+"#
+        .lines()
+        .map(String::from)
+        .collect::<Vec<String>>();
+        let hands = Hands::parse(&input, &RuleSet::part2()).unwrap();
+        let report = hands.report();
+        assert_eq!(
+            report[0],
+            RankedHand {
+                cards: "KTJJT".to_string(),
+                resolved: "KTTTT".to_string(),
+                hand_type: HandType::FourOfAKind,
+                rank: 5,
+                bid: 220,
+                winnings: 1100,
+            }
+        );
+        assert_eq!(
+            report.iter().map(|h| h.rank).collect::<Vec<usize>>(),
+            vec![5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            report.iter().map(|h| h.winnings).sum::<usize>(),
+            hands.total_winnings()
+        );
+        assert!(hands.ties().is_empty());
+        assert!(hands
+            .to_report()
+            .contains("KTJJT    KTTTT    four-of-a-kind"));
+    }
+
+    #[test]
+    fn test_ties() {
+        let input = ["KK677 28", "32T3K 765", "KK677 3", "AAAAA 1", "KK677 28"]
+            .map(String::from)
+            .to_vec();
+        let hands = Hands::parse(&input, &RuleSet::part1()).unwrap();
+        let ties = hands.ties();
+        assert_eq!(ties.len(), 1);
+        assert_eq!(ties[0].cards, "KK677");
+        assert_eq!(ties[0].ranks, 2..5);
+        assert_eq!(ties[0].bids, vec![28, 3, 28]);
+        assert_eq!(
+            ties[0].to_string(),
+            "tie: KK677 appears 3 times, ranks 2 to 4, bids [28, 3, 28]"
+        );
+        assert_eq!(
+            ties[0],
+            Tie {
+                cards: "KK677".to_string(),
+                ranks: 2..5,
+                bids: vec![28, 3, 28],
+            }
+        );
+    }
 }
@@ -1,96 +1,605 @@
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use utils::num::{crt, CrtError};
 use utils::read_lines;
 
-fn highest_common_factor(a: usize, b: usize) -> usize {
-    let max = usize::min(a, b);
-    for i in (1..=max).rev() {
-        if a % i == 0 && b % i == 0 {
-            return i;
+// The steps at which a ghost is on an end node: `start`, and then every `period` steps. A
+// period of 0 means that the ghost is there only once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Arrivals {
+    start: usize,
+    period: usize,
+}
+
+impl Arrivals {
+    fn contains(&self, step: usize) -> bool {
+        if self.period == 0 {
+            step == self.start
+        } else {
+            step >= self.start && (step - self.start).is_multiple_of(self.period)
+        }
+    }
+
+    // The steps at which both ghosts arrive, if there are any. Fails if the steps don't fit
+    // in a usize.
+    fn intersect(&self, other: &Arrivals) -> Result<Option<Arrivals>, CrtError> {
+        if self.period == 0 {
+            return Ok(other.contains(self.start).then_some(*self));
+        }
+        if other.period == 0 {
+            return Ok(self.contains(other.start).then_some(*other));
+        }
+        let (x, period) = match crt([(self.start, self.period), (other.start, other.period)]) {
+            Ok(solution) => solution,
+            Err(CrtError::NoSolution) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // Both ghosts need to have actually started their cycles.
+        let min = usize::max(self.start, other.start);
+        let start = if x >= min {
+            x
+        } else {
+            (min - x)
+                .div_ceil(period)
+                .checked_mul(period)
+                .and_then(|steps| steps.checked_add(x))
+                .ok_or(CrtError::Overflow)?
+        };
+        Ok(Some(Arrivals { start, period }))
+    }
+}
+
+// The walk of a ghost: after `offset` steps it enters a cycle of `length` steps, which it
+// then repeats forever. `hits` are the steps at which it is on an end node before it
+// completes the cycle for the first time.
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    offset: usize,
+    length: usize,
+    hits: Vec<usize>,
+}
+
+impl Cycle {
+    // The hits before the cycle only happen once, the ones in the cycle happen again every
+    // `length` steps.
+    fn arrivals(&self) -> Vec<Arrivals> {
+        self.hits
+            .iter()
+            .map(|hit| Arrivals {
+                start: *hit,
+                period: if *hit < self.offset { 0 } else { self.length },
+            })
+            .collect()
+    }
+}
+
+// Selects nodes by their name.
+#[derive(Debug, Clone)]
+enum NodePattern {
+    Exact(String),
+    Suffix(String),
+    Regex(Regex),
+}
+
+impl NodePattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NodePattern::Exact(exact) => name == exact,
+            NodePattern::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            NodePattern::Regex(regex) => regex.is_match(name),
         }
     }
-    1
 }
 
-fn least_common_multiple(a: usize, b: usize) -> usize {
-    (a * b) / highest_common_factor(a, b)
+impl PartialEq for NodePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
 }
 
+impl Display for NodePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodePattern::Exact(exact) => write!(f, "exact:{exact}"),
+            NodePattern::Suffix(suffix) => write!(f, "suffix:{suffix}"),
+            NodePattern::Regex(regex) => write!(f, "regex:{regex}"),
+        }
+    }
+}
+
+// Parses the patterns written as in `Display`, e.g. `suffix:Z`.
+impl TryFrom<&str> for NodePattern {
+    type Error = ParseErrorKind;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || ParseErrorKind::InvalidPattern(value.to_string());
+        match value.split_once(':').ok_or_else(invalid)? {
+            ("exact", exact) => Ok(NodePattern::Exact(exact.to_string())),
+            ("suffix", suffix) => Ok(NodePattern::Suffix(suffix.to_string())),
+            ("regex", regex) => Regex::new(regex)
+                .map(NodePattern::Regex)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Where a node leads after following the whole path once.
+#[derive(Debug, Clone, PartialEq)]
+struct Jump {
+    to: u32,
+    // The steps along the way at which we are on an end node, from 0 for the node itself.
+    hits: Vec<usize>,
+}
+
+#[derive(Debug)]
 struct Map {
-    // a hashmap in which the key is the name of the node and the value represents the neighbors
-    // in order Left, Right.
-    inner: HashMap<String, Vec<String>>,
+    // The names of the nodes, indexed by their ID.
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    // The names of the directions, `L` and `R` by default, which are the instructions of the
+    // path.
+    directions: Vec<char>,
+    // The neighbors of all the nodes, one per direction, one node after the other. The
+    // neighbor of node `n` in direction `d` is at `n * directions.len() + d`.
+    nodes: Vec<u32>,
+    // Whether each node is a start or an end for the ghosts. By default, these are the
+    // nodes whose names end with `A` and `Z`.
+    starts: Vec<bool>,
+    ends: Vec<bool>,
+    // The index of the direction of each instruction.
     path: Vec<usize>,
+    // The jump of each node for the ghosts, so that they can be moved a whole path at a time.
+    jumps: Vec<Jump>,
+}
+
+// What went wrong while parsing the network.
+#[derive(Debug, Clone, PartialEq)]
+enum ParseErrorKind {
+    // The first line, with the instructions, is empty.
+    MissingPath,
+    // An instruction that is not one of the directions, which are `L` and `R` by default.
+    InvalidDirection(char),
+    // A node is not of the form `<name> = (<neighbor>, <neighbor>, ...)`.
+    InvalidNode(String),
+    // A node must have one neighbor per direction.
+    NeighborCount { expected: usize, found: usize },
+    // A node that is defined more than once.
+    DuplicateNode(String),
+    // A node that is a neighbor of another one, but is never defined.
+    UndefinedNode(String),
+    // A node pattern that is not `exact:<name>`, `suffix:<suffix>` or `regex:<regex>`.
+    InvalidPattern(String),
+}
+
+type ParseError = utils::ParseError<ParseErrorKind>;
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::MissingPath => write!(f, "the instructions are missing"),
+            ParseErrorKind::InvalidDirection(c) => write!(f, "`{c}` is not a valid instruction"),
+            ParseErrorKind::InvalidNode(line) => write!(
+                f,
+                "`{line}` is not of the form `<name> = (<neighbor>, <neighbor>, ...)`"
+            ),
+            ParseErrorKind::NeighborCount { expected, found } => {
+                write!(f, "expected {expected} neighbors, found {found}")
+            }
+            ParseErrorKind::DuplicateNode(name) => {
+                write!(f, "the node `{name}` is already defined")
+            }
+            ParseErrorKind::UndefinedNode(name) => {
+                write!(f, "the node `{name}` is never defined")
+            }
+            ParseErrorKind::InvalidPattern(pattern) => write!(
+                f,
+                "`{pattern}` is not of the form `exact:<name>`, `suffix:<suffix>` or `regex:<regex>`"
+            ),
+        }
+    }
+}
+
+// Errors returned when travelling through the network.
+#[derive(Debug, Clone, PartialEq)]
+enum TravelError {
+    UnknownNode(String),
+    // We came back to the same node at the same point of the path without finding any of
+    // the destinations, so we would go around in circles forever.
+    Unreachable {
+        start: String,
+        destination: NodePattern,
+    },
+    // No node matches the start pattern of the ghosts.
+    NoGhosts,
+    // The steps at which the ghosts meet don't fit in a usize.
+    Crt(CrtError),
+}
+
+impl From<CrtError> for TravelError {
+    fn from(value: CrtError) -> Self {
+        TravelError::Crt(value)
+    }
+}
+
+impl Display for TravelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TravelError::UnknownNode(name) => write!(f, "there is no node `{name}`"),
+            TravelError::Unreachable { start, destination } => write!(
+                f,
+                "no node matching `{destination}` can be reached from `{start}`"
+            ),
+            TravelError::NoGhosts => write!(f, "there are no ghosts"),
+            TravelError::Crt(e) => write!(f, "{e}"),
+        }
+    }
 }
 
 impl From<Vec<String>> for Map {
     fn from(value: Vec<String>) -> Self {
-        let path = value[0]
-            .replace('R', "1")
-            .replace('L', "0")
+        Map::parse(&value).unwrap()
+    }
+}
+
+impl Map {
+    fn parse(lines: &[String]) -> Result<Self, ParseError> {
+        let error = |line: usize, kind: ParseErrorKind| ParseError {
+            line: line + 1,
+            kind,
+        };
+        // The directions can be given before the instructions, e.g. `LUR: LLURL`.
+        let first = lines
+            .first()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .ok_or(error(0, ParseErrorKind::MissingPath))?;
+        let (directions, instructions) = match first.split_once(':') {
+            Some((directions, instructions)) => {
+                (directions.trim().chars().collect(), instructions.trim())
+            }
+            None => (vec!['L', 'R'], first),
+        };
+        let path = instructions
             .chars()
-            .map(|c| c.to_digit(10).unwrap() as usize)
-            .collect();
-        let map = value[2..]
+            .map(|c| {
+                directions
+                    .iter()
+                    .position(|d| *d == c)
+                    .ok_or(error(0, ParseErrorKind::InvalidDirection(c)))
+            })
+            .collect::<Result<Vec<usize>, ParseError>>()?;
+
+        let mut nodes = vec![];
+        for (index, line) in lines.iter().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let node = line.split_once(" = ").and_then(|(node, neighbors)| {
+                let neighbors = neighbors.trim().strip_prefix('(')?.strip_suffix(')')?;
+                let neighbors = neighbors
+                    .split(',')
+                    .map(|n| n.trim())
+                    .collect::<Vec<&str>>();
+                Some((index, node.trim(), neighbors))
+            });
+            let node = node.ok_or(error(index, ParseErrorKind::InvalidNode(line.clone())))?;
+            if node.2.len() != directions.len() {
+                return Err(error(
+                    index,
+                    ParseErrorKind::NeighborCount {
+                        expected: directions.len(),
+                        found: node.2.len(),
+                    },
+                ));
+            }
+            nodes.push(node);
+        }
+
+        // The IDs are given in the order in which the nodes are defined.
+        let mut names = vec![];
+        let mut ids = HashMap::new();
+        for (index, name, _) in nodes.iter() {
+            if ids.insert(name.to_string(), names.len() as u32).is_some() {
+                return Err(error(
+                    *index,
+                    ParseErrorKind::DuplicateNode(name.to_string()),
+                ));
+            }
+            names.push(name.to_string());
+        }
+        let nodes = nodes
             .iter()
-            .map(|line| {
-                let (node, neighbors) = line.split_once(" = ").unwrap();
-                let neighbors = neighbors.replace(['(', ')'], "");
-                let (l, r) = neighbors.split_once(", ").unwrap();
-                (node.to_string(), vec![l.to_string(), r.to_string()])
+            .flat_map(|(index, _, neighbors)| {
+                neighbors.iter().map(|n| {
+                    ids.get(*n)
+                        .copied()
+                        .ok_or_else(|| error(*index, ParseErrorKind::UndefinedNode(n.to_string())))
+                })
             })
-            .collect();
-        Map { path, inner: map }
+            .collect::<Result<Vec<u32>, ParseError>>()?;
+
+        let map = Map {
+            names,
+            ids,
+            directions,
+            nodes,
+            starts: vec![],
+            ends: vec![],
+            path,
+            jumps: vec![],
+        };
+        Ok(map.with_ghosts(
+            &NodePattern::Suffix("A".to_string()),
+            &NodePattern::Suffix("Z".to_string()),
+        ))
     }
-}
 
-impl Map {
-    // Returns how many hops there are between start and destionation.
-    fn travel_from(&self, start: &String, destination_pattern: &str) -> usize {
+    // Sets the nodes where the ghosts start and end.
+    fn with_ghosts(mut self, starts: &NodePattern, ends: &NodePattern) -> Self {
+        self.starts = self.matching(starts);
+        self.ends = self.matching(ends);
+        self.jumps = self.jump_table(&self.ends);
+        self
+    }
+
+    // Whether each node matches the pattern.
+    fn matching(&self, pattern: &NodePattern) -> Vec<bool> {
+        self.names.iter().map(|n| pattern.matches(n)).collect()
+    }
+
+    fn neighbor(&self, node: usize, direction: usize) -> usize {
+        self.nodes[node * self.directions.len() + direction] as usize
+    }
+
+    // Follows the whole path from every node, noting when we are on one of the `ends`.
+    fn jump_table(&self, ends: &[bool]) -> Vec<Jump> {
+        (0..self.names.len())
+            .map(|node| {
+                let mut cur = node;
+                let mut hits = vec![];
+                for (step, direction) in self.path.iter().enumerate() {
+                    if ends[cur] {
+                        hits.push(step);
+                    }
+                    cur = self.neighbor(cur, *direction);
+                }
+                Jump {
+                    to: cur as u32,
+                    hits,
+                }
+            })
+            .collect()
+    }
+
+    // Returns how many hops there are between start and the first node matching destination.
+    fn travel_from(&self, start: &str, destination: &NodePattern) -> Result<usize, TravelError> {
+        let jumps = self.jump_table(&self.matching(destination));
+        let mut visited = vec![false; self.names.len()];
         let mut hops = 0;
-        let mut cur = start;
-        let mut path_index = 0;
+        let mut cur = *self
+            .ids
+            .get(start)
+            .ok_or_else(|| TravelError::UnknownNode(start.to_string()))?
+            as usize;
+
+        // We skip whole paths until there is an end node in the next one. If we start a path
+        // from the same node twice, we are going in circles.
+        loop {
+            let jump = &jumps[cur];
+            if let Some(hit) = jump.hits.first() {
+                return Ok(hops + hit);
+            }
+            if visited[cur] {
+                return Err(TravelError::Unreachable {
+                    start: start.to_string(),
+                    destination: destination.clone(),
+                });
+            }
+            visited[cur] = true;
+            hops += self.path.len();
+            cur = jump.to as usize;
+        }
+    }
+
+    // Walks from start until the ghost is in the same node at the same point of the path as
+    // before, after which it can only repeat the same steps. Any cycle goes through the
+    // start of the path, so it is enough to look at the nodes where the path starts.
+    fn find_cycle(&self, start: &str) -> Cycle {
+        let mut visited = vec![None; self.names.len()];
+        let mut hits = vec![];
+        let mut cur = self.ids[start] as usize;
+        let mut block = 0;
+
+        loop {
+            if let Some(first) = visited[cur] {
+                return Cycle {
+                    offset: first * self.path.len(),
+                    length: (block - first) * self.path.len(),
+                    hits,
+                };
+            }
+            visited[cur] = Some(block);
+            let jump = &self.jumps[cur];
+            hits.extend(jump.hits.iter().map(|hit| block * self.path.len() + hit));
+            cur = jump.to as usize;
+            block += 1;
+        }
+    }
+
+    // The edges that a ghost follows forever once it is in its cycle, as (node, direction).
+    fn cycle_edges(&self, start: &str) -> HashSet<(usize, usize)> {
+        let cycle = self.find_cycle(start);
+        let mut cur = self.ids[start] as usize;
+        let mut edges = HashSet::new();
+        for step in 0..cycle.offset + cycle.length {
+            let direction = self.path[step % self.path.len()];
+            if step >= cycle.offset {
+                edges.insert((cur, direction));
+            }
+            cur = self.neighbor(cur, direction);
+        }
+        edges
+    }
 
-        while !cur.ends_with(destination_pattern) {
-            let direction = self.path[path_index];
-            cur = &self.inner.get(cur).unwrap()[direction];
-            hops += 1;
-            path_index += 1;
-            if path_index == self.path.len() {
-                path_index = 0;
+    // Exports the network to Graphviz. Start nodes are boxes and end nodes are red. With
+    // `ghost_cycles`, the edges of the cycle of each ghost are drawn in its own colour.
+    fn to_dot(&self, ghost_cycles: bool) -> String {
+        const COLOURS: [&str; 6] = ["blue", "darkgreen", "orange", "purple", "brown", "cyan"];
+        let cycles = if ghost_cycles {
+            self.names
+                .iter()
+                .zip(self.starts.iter())
+                .filter(|(_, is_start)| **is_start)
+                .map(|(start, _)| self.cycle_edges(start))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let mut dot = String::from("digraph network {\n");
+        for (node, name) in self.names.iter().enumerate() {
+            let mut attributes = vec![];
+            if self.starts[node] {
+                attributes.push("shape=box");
+            }
+            if self.ends[node] {
+                attributes.push("style=filled, fillcolor=lightcoral");
+            }
+            if attributes.is_empty() {
+                dot.push_str(&format!("    \"{name}\";\n"));
+            } else {
+                dot.push_str(&format!("    \"{name}\" [{}];\n", attributes.join(", ")));
+            }
+        }
+        for (node, name) in self.names.iter().enumerate() {
+            // The directions that lead to the same neighbor share an edge.
+            let mut neighbors: Vec<(usize, Vec<usize>)> = vec![];
+            for direction in 0..self.directions.len() {
+                let neighbor = self.neighbor(node, direction);
+                match neighbors.iter_mut().find(|(n, _)| *n == neighbor) {
+                    Some((_, directions)) => directions.push(direction),
+                    None => neighbors.push((neighbor, vec![direction])),
+                }
+            }
+            for (neighbor, directions) in neighbors {
+                let label = directions
+                    .iter()
+                    .map(|d| self.directions[*d].to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                let colours = cycles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, edges)| directions.iter().any(|d| edges.contains(&(node, *d))))
+                    .map(|(ghost, _)| COLOURS[ghost % COLOURS.len()])
+                    .collect::<Vec<&str>>();
+                let style = if colours.is_empty() {
+                    String::new()
+                } else {
+                    format!(", color=\"{}\", penwidth=2", colours.join(":"))
+                };
+                dot.push_str(&format!(
+                    "    \"{name}\" -> \"{}\" [label=\"{label}\"{style}];\n",
+                    self.names[neighbor]
+                ));
             }
         }
-        hops
+        dot.push('}');
+        dot
     }
 
-    fn travel_all(&self) -> usize {
-        // We calculate how many hops each of the new input needs to reach a `Z`.
-        // Then we just find out what is the least common multiple of all of them.
-        let hops = self
-            .inner
-            .keys()
-            .filter(|n| n.ends_with('A'))
-            .map(|cur| self.travel_from(cur, "Z"))
-            .collect::<Vec<usize>>();
-        hops.iter()
-            .fold(1, |acc, hops| least_common_multiple(acc, *hops))
+    // Returns the first step at which all the ghosts are on an end node, or None if they are
+    // never all there at the same time. Fails if there are no ghosts, or if that step doesn't
+    // fit in a usize.
+    fn travel_all(&self) -> Result<Option<usize>, TravelError> {
+        if !self.starts.contains(&true) {
+            return Err(TravelError::NoGhosts);
+        }
+        // Each ghost arrives at some steps before its cycle, and then regularly in its cycle.
+        // We keep the steps at which all the ghosts seen so far arrive together, starting from
+        // all the steps, and combine them with the arrivals of each ghost with the Chinese
+        // remainder theorem.
+        let mut arrivals = vec![Arrivals {
+            start: 0,
+            period: 1,
+        }];
+        let starts = self.names.iter().zip(self.starts.iter());
+        for (start, _) in starts.filter(|(_, is_start)| **is_start) {
+            let ghost = self.find_cycle(start).arrivals();
+            arrivals = arrivals
+                .iter()
+                .flat_map(|a| ghost.iter().map(|b| a.intersect(b)))
+                .filter_map(Result::transpose)
+                .collect::<Result<Vec<Arrivals>, CrtError>>()?;
+            arrivals.sort_by_key(|a| (a.start, a.period));
+            arrivals.dedup();
+        }
+        Ok(arrivals.iter().map(|a| a.start).min())
     }
 }
 
 fn main() {
     let path = PathBuf::from("src/day8/input.txt");
     let input = read_lines(path);
-    let map = Map::from(input);
-    println!("Part 1: {}", map.travel_from(&"AAA".to_string(), "ZZZ"));
-    println!("Part 2: {}", map.travel_all());
+    let map = match Map::parse(&input) {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("Invalid network: {e}");
+            return;
+        }
+    };
+    match map.travel_from("AAA", &NodePattern::Exact("ZZZ".to_string())) {
+        Ok(steps) => println!("Part 1: {steps}"),
+        Err(e) => eprintln!("Part 1: {e}"),
+    }
+
+    // The ghosts can start and end on other nodes, e.g. `day8 suffix:A regex:^[XYZ]..$`, and
+    // the network can be printed for Graphviz with `--dot`, or `--dot-cycles` to colour the
+    // cycles of the ghosts.
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let patterns = args
+        .iter()
+        .map(|arg| NodePattern::try_from(arg.as_str()))
+        .collect::<Result<Vec<NodePattern>, ParseErrorKind>>();
+    let map = match patterns.as_deref() {
+        Ok([]) => map,
+        Ok([starts, ends]) => map.with_ghosts(starts, ends),
+        Ok(_) => {
+            eprintln!("Usage: day8 [--dot | --dot-cycles] [<start pattern> <end pattern>]");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Invalid pattern: {e}");
+            return;
+        }
+    };
+    match map.travel_all() {
+        Ok(Some(steps)) => println!("Part 2: {steps}"),
+        Ok(None) => println!("Part 2: the ghosts are never all on an end node at the same time"),
+        Err(e) => eprintln!("Part 2: {e}"),
+    }
+    if flags.iter().any(|flag| flag == "--dot") {
+        println!("{}", map.to_dot(false));
+    }
+    if flags.iter().any(|flag| flag == "--dot-cycles") {
+        println!("{}", map.to_dot(true));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{least_common_multiple, Map};
+    use crate::{Arrivals, Cycle, Jump, Map, NodePattern, ParseError, ParseErrorKind, TravelError};
+    use regex::Regex;
+    use utils::num::CrtError;
 
     #[test]
     fn test_part1() {
+        let zzz = NodePattern::Exact("ZZZ".to_string());
         let input: Vec<String> = r#"RL
 
 AAA = (BBB, CCC)
@@ -104,7 +613,7 @@ ZZZ = (ZZZ, ZZZ)"#
             .map(String::from)
             .collect();
         let map = Map::from(input);
-        assert_eq!(map.travel_from(&"AAA".to_string(), "ZZZ"), 2);
+        assert_eq!(map.travel_from("AAA", &zzz), Ok(2));
 
         let input: Vec<String> = r#"LLR
 
@@ -115,7 +624,7 @@ ZZZ = (ZZZ, ZZZ)"#
             .map(String::from)
             .collect();
         let map = Map::from(input);
-        assert_eq!(map.travel_from(&"AAA".to_string(), "ZZZ"), 6);
+        assert_eq!(map.travel_from("AAA", &zzz), Ok(6));
     }
 
     #[test]
@@ -134,12 +643,312 @@ XXX = (XXX, XXX)"#
             .map(String::from)
             .collect();
         let map = Map::from(input);
-        assert_eq!(map.travel_all(), 6);
+        assert_eq!(map.travel_all(), Ok(Some(6)));
+    }
+
+    #[test]
+    fn test_cycles() {
+        // The first ghost is on 11Z every 2 steps from step 2, the second one every 3 steps
+        // from step 1, so the first hits alone would give 2 instead of 4.
+        let input: Vec<String> = r#"L
+
+11A = (11B, 11B)
+11B = (11Z, 11Z)
+11Z = (11C, 11C)
+11C = (11Z, 11Z)
+22A = (22Z, 22Z)
+22Z = (22B, 22B)
+22B = (22C, 22C)
+22C = (22Z, 22Z)"#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(
+            map.find_cycle("11A"),
+            Cycle {
+                offset: 2,
+                length: 2,
+                hits: vec![2],
+            }
+        );
+        assert_eq!(
+            map.find_cycle("22A"),
+            Cycle {
+                offset: 1,
+                length: 3,
+                hits: vec![1],
+            }
+        );
+        assert_eq!(map.travel_all(), Ok(Some(4)));
+
+        // The ghosts are never on an end node at the same time: the first one only passes
+        // through 33Z once, at step 1, and the second one is there at even steps.
+        let input: Vec<String> = r#"L
+
+33A = (33Z, 33Z)
+33Z = (33B, 33B)
+33B = (33B, 33B)
+44A = (44B, 44B)
+44B = (44Z, 44Z)
+44Z = (44B, 44B)"#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(
+            map.find_cycle("33A").arrivals(),
+            vec![Arrivals {
+                start: 1,
+                period: 0,
+            }]
+        );
+        assert_eq!(map.travel_all(), Ok(None));
+    }
+
+    #[test]
+    fn test_arrivals() {
+        let a = Arrivals {
+            start: 2,
+            period: 4,
+        };
+        let b = Arrivals {
+            start: 28,
+            period: 6,
+        };
+        // 10 and 22 are solutions, but the second ghost only arrives from step 28.
+        assert_eq!(
+            a.intersect(&b),
+            Ok(Some(Arrivals {
+                start: 34,
+                period: 12,
+            }))
+        );
+        let once = Arrivals {
+            start: 6,
+            period: 0,
+        };
+        assert_eq!(a.intersect(&once), Ok(Some(once)));
+        assert_eq!(b.intersect(&once), Ok(None));
+        let odd = Arrivals {
+            start: 3,
+            period: 6,
+        };
+        assert_eq!(a.intersect(&odd), Ok(None));
+        // The ghosts arrive together every lcm(2^64 - 1, 2^64 - 2) steps, which is too many.
+        let huge = Arrivals {
+            start: 0,
+            period: usize::MAX,
+        };
+        let other = Arrivals {
+            start: 0,
+            period: usize::MAX - 1,
+        };
+        assert_eq!(huge.intersect(&other), Err(CrtError::Overflow));
     }
 
     #[test]
-    fn test_number_ops() {
-        assert_eq!(least_common_multiple(5, 15), 15);
-        assert_eq!(least_common_multiple(1, 2), 2);
+    fn test_jumps() {
+        let zzz = NodePattern::Exact("ZZZ".to_string());
+        let input: Vec<String> = r#"LLR
+
+AAA = (BBB, BBB)
+BBB = (AAA, ZZZ)
+ZZZ = (ZZZ, ZZZ)"#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        assert_eq!(map.names, vec!["AAA", "BBB", "ZZZ"]);
+        assert_eq!(map.nodes, vec![1, 1, 0, 2, 2, 2]);
+        assert_eq!(map.starts, vec![true, false, false]);
+        assert_eq!(map.ends, vec![false, false, true]);
+        assert_eq!(
+            map.jumps,
+            vec![
+                Jump {
+                    to: 1,
+                    hits: vec![],
+                },
+                Jump {
+                    to: 2,
+                    hits: vec![],
+                },
+                Jump {
+                    to: 2,
+                    hits: vec![0, 1, 2],
+                },
+            ]
+        );
+
+        // A ring of 1000 nodes, of which the path only moves one node forward, so it takes
+        // almost a million steps to go around it.
+        let mut input = vec!["R".repeat(999) + "L", String::new()];
+        let name = |i: usize| match i {
+            0 => "AAA".to_string(),
+            999 => "ZZZ".to_string(),
+            _ => format!("{i:03}"),
+        };
+        for i in 0..1000 {
+            input.push(format!(
+                "{} = ({}, {})",
+                name(i),
+                name((i + 1) % 1000),
+                name(i)
+            ));
+        }
+        let map = Map::from(input);
+        assert_eq!(map.travel_from("AAA", &zzz), Ok(999_000));
+    }
+
+    #[test]
+    fn test_errors() {
+        let zzz = NodePattern::Exact("ZZZ".to_string());
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        let map = Map::from(lines(
+            "L\n\nAAA = (BBB, BBB)\nBBB = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)",
+        ));
+        assert_eq!(
+            map.travel_from("AAA", &zzz),
+            Err(TravelError::Unreachable {
+                start: "AAA".to_string(),
+                destination: zzz.clone(),
+            })
+        );
+        assert_eq!(map.travel_from("ZZZ", &zzz), Ok(0));
+        assert_eq!(
+            map.travel_from("CCC", &zzz),
+            Err(TravelError::UnknownNode("CCC".to_string()))
+        );
+
+        let error = |line: usize, kind: ParseErrorKind| ParseError { line, kind };
+        assert_eq!(
+            Map::parse(&lines("LR\n\nAAA = (BBB, CCC)\nBBB = (AAA, AAA)")).unwrap_err(),
+            error(3, ParseErrorKind::UndefinedNode("CCC".to_string()))
+        );
+        assert_eq!(
+            Map::parse(&lines("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)")).unwrap_err(),
+            error(4, ParseErrorKind::DuplicateNode("AAA".to_string()))
+        );
+        assert_eq!(
+            Map::parse(&lines("LXR\n\nAAA = (AAA, AAA)")).unwrap_err(),
+            error(1, ParseErrorKind::InvalidDirection('X'))
+        );
+        assert_eq!(
+            Map::parse(&lines("\n\nAAA = (AAA, AAA)")).unwrap_err(),
+            error(1, ParseErrorKind::MissingPath)
+        );
+        assert_eq!(
+            Map::parse(&lines("L\n\nAAA = AAA, AAA")).unwrap_err(),
+            error(3, ParseErrorKind::InvalidNode("AAA = AAA, AAA".to_string()))
+        );
+        assert_eq!(
+            Map::parse(&lines("LR\n\nAAA = (BBB, CCC)"))
+                .unwrap_err()
+                .to_string(),
+            "line 3: the node `BBB` is never defined"
+        );
+    }
+
+    #[test]
+    fn test_patterns() {
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<String>>();
+        let network = "AAA = (BBB, CCC, AAA)
+BBB = (AAA, ZZZ, BBB)
+CCC = (CCC, CCC, ZZZ)
+ZZZ = (ZZZ, ZZZ, ZZZ)";
+        let zzz = NodePattern::try_from("exact:ZZZ").unwrap();
+        let map = Map::parse(&lines(&format!("LUR: LUR\n\n{network}"))).unwrap();
+        assert_eq!(map.directions, vec!['L', 'U', 'R']);
+        assert_eq!(map.path, vec![0, 1, 2]);
+        assert_eq!(map.travel_from("AAA", &zzz), Ok(2));
+        let map = Map::parse(&lines(&format!("LUR: UR\n\n{network}"))).unwrap();
+        assert_eq!(map.travel_from("AAA", &zzz), Ok(2));
+        assert_eq!(
+            map.travel_from("AAA", &NodePattern::Suffix("C".to_string())),
+            Ok(1)
+        );
+
+        // By default, the only ghost starts on `AAA` and ends on `ZZZ`.
+        assert_eq!(map.travel_all(), Ok(Some(2)));
+        let map = map.with_ghosts(
+            &NodePattern::try_from("regex:^[AB]").unwrap(),
+            &NodePattern::Regex(Regex::new("^(CCC|ZZZ)$").unwrap()),
+        );
+        assert_eq!(map.starts, vec![true, true, false, false]);
+        assert_eq!(map.ends, vec![false, false, true, true]);
+        assert_eq!(map.travel_all(), Ok(Some(1)));
+        // No ghost starts on a node that doesn't exist.
+        let map = map.with_ghosts(
+            &NodePattern::try_from("exact:XXX").unwrap(),
+            &NodePattern::try_from("suffix:Z").unwrap(),
+        );
+        assert_eq!(map.starts, vec![false; 4]);
+        assert_eq!(map.travel_all(), Err(TravelError::NoGhosts));
+
+        assert_eq!(
+            Map::parse(&lines(&format!("LUR: LXR\n\n{network}"))).unwrap_err(),
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::InvalidDirection('X'),
+            }
+        );
+        assert_eq!(
+            Map::parse(&lines(&format!("LR\n\n{network}"))).unwrap_err(),
+            ParseError {
+                line: 3,
+                kind: ParseErrorKind::NeighborCount {
+                    expected: 2,
+                    found: 3,
+                },
+            }
+        );
+        assert_eq!(
+            NodePattern::try_from("prefix:A"),
+            Err(ParseErrorKind::InvalidPattern("prefix:A".to_string()))
+        );
+        assert_eq!(
+            NodePattern::try_from("regex:(").map(|p| p.to_string()),
+            Err(ParseErrorKind::InvalidPattern("regex:(".to_string()))
+        );
+        assert_eq!(zzz.to_string(), "exact:ZZZ");
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let input: Vec<String> = r#"LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)"#
+            .lines()
+            .map(String::from)
+            .collect();
+        let map = Map::from(input);
+        let dot = map.to_dot(false);
+        assert!(dot.starts_with("digraph network {\n"));
+        assert!(dot.ends_with('}'));
+        assert!(dot.contains("    \"11A\" [shape=box];\n"));
+        assert!(dot.contains("    \"11Z\" [style=filled, fillcolor=lightcoral];\n"));
+        assert!(dot.contains("    \"XXX\";\n"));
+        assert!(dot.contains("    \"11A\" -> \"11B\" [label=\"L\"];\n"));
+        assert!(dot.contains("    \"22B\" -> \"22C\" [label=\"L,R\"];\n"));
+        assert_eq!(dot.matches("->").count(), 12);
+
+        // The first ghost goes around 11B -> 11Z -> 11B, the second one around
+        // 22B -> 22C -> 22Z -> 22B, both with a different colour. 22A is only visited once.
+        let dot = map.to_dot(true);
+        assert!(dot.contains("    \"11B\" -> \"11Z\" [label=\"R\", color=\"blue\", penwidth=2];\n"));
+        assert!(dot.contains("    \"11Z\" -> \"11B\" [label=\"L\", color=\"blue\", penwidth=2];\n"));
+        assert!(dot.contains(
+            "    \"22C\" -> \"22Z\" [label=\"L,R\", color=\"darkgreen\", penwidth=2];\n"
+        ));
+        assert!(dot.contains("    \"22A\" -> \"22B\" [label=\"L\"];\n"));
+        assert_eq!(dot.matches("penwidth").count(), 5);
     }
 }
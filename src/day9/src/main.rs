@@ -1,4 +1,7 @@
+use std::env;
+use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use utils::num::gcd_i128;
 use utils::{as_vec, read_lines};
 
 struct Report {
@@ -8,64 +11,111 @@ struct Report {
 #[derive(Debug)]
 struct ValueHistory {
     inner: Vec<isize>,
+    // The Newton forward differences of the history: the first value of each row of the
+    // difference table, up to the first row that is all zeroes. The value at any index `k` is
+    // then `newton[0] + newton[1] * C(k, 1) + newton[2] * C(k, 2) + ...`.
+    newton: Result<Vec<i128>, HistoryError>,
+}
+
+// Why a history can't be extrapolated.
+#[derive(Debug, Clone, PartialEq)]
+enum HistoryError {
+    Empty,
+    // The differences never become all zeroes within the length of the history, so we
+    // can't tell which polynomial the values follow. A history of n values can only show a
+    // polynomial of degree n - 2 or less, so this is the case of any single value other than
+    // 0, and of any two different values.
+    NotPolynomial(usize),
+    // A difference or a prediction doesn't fit in an i128.
+    Overflow,
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Empty => write!(f, "the history is empty"),
+            HistoryError::NotPolynomial(length) => write!(
+                f,
+                "the sequence is not polynomial within its length of {length} values"
+            ),
+            HistoryError::Overflow => write!(f, "the values don't fit in an i128"),
+        }
+    }
 }
 
 impl From<&String> for ValueHistory {
     fn from(value: &String) -> Self {
-        let inner = as_vec(value.as_str());
-        ValueHistory { inner }
+        ValueHistory::new(as_vec(value.as_str()))
     }
 }
 
-fn compute_diffs_array(inner: &[isize]) -> Vec<isize> {
-    inner
-        .windows(2)
-        .map(|v| v[1] - v[0])
-        .collect::<Vec<isize>>()
+fn compute_diffs_array(inner: &[i128]) -> Option<Vec<i128>> {
+    inner.windows(2).map(|v| v[1].checked_sub(v[0])).collect()
 }
 
-fn all_zeroes(inner: &[isize]) -> bool {
+fn all_zeroes(inner: &[i128]) -> bool {
     inner.iter().all(|v| *v == 0)
 }
 
-impl ValueHistory {
-    fn predict_next(&self) -> isize {
-        let mut diffs = compute_diffs_array(&self.inner);
-        let mut next_value = *diffs.last().unwrap();
-        while !all_zeroes(&diffs) {
-            diffs = compute_diffs_array(&diffs);
-            next_value += *diffs.last().unwrap_or(&0);
+// Computes the Newton forward differences of the values, which must reach a row of zeroes.
+fn newton_coefficients(values: &[isize]) -> Result<Vec<i128>, HistoryError> {
+    if values.is_empty() {
+        return Err(HistoryError::Empty);
+    }
+    let mut coefficients = vec![];
+    let mut diffs = values.iter().map(|v| *v as i128).collect::<Vec<i128>>();
+    while !all_zeroes(&diffs) {
+        // A row with a single value that is not 0 is the last one, so we never found a row
+        // of zeroes.
+        if diffs.len() == 1 {
+            return Err(HistoryError::NotPolynomial(values.len()));
         }
+        coefficients.push(diffs[0]);
+        diffs = compute_diffs_array(&diffs).ok_or(HistoryError::Overflow)?;
+    }
+    Ok(coefficients)
+}
 
-        next_value + self.inner.last().unwrap()
-    }
-
-    fn predict_previous(&self) -> isize {
-        let mut diffs = compute_diffs_array(&self.inner);
-
-        // We keep the difference from the first extrapolation in `previous_diff`.
-        // For finding out how much is `previous_diff`, we have to use the
-        // following formula:
-        // val_0 = val_1 - diff_1[0];
-        // diff_1[0] = diff_1[1] - diff_2[1];
-        // diff_2[0] = diff_2[1] - diff_3[1];
-        // ...
-        // Thus, val_0 = val_1 - diff_1[1] + diff_2[1] - diff_3[1] + diff4[1] ....
-        // In this notation diff_1[0] we convey the following meaning:
-        //  * _n -> the extrapolation number
-        //  * diff_n -> array containing all the nth extrapolations
-        let mut previous_diff = diffs[0];
-        let mut iter = 1;
-        while !all_zeroes(&diffs) {
-            diffs = compute_diffs_array(&diffs);
-            if iter % 2 == 0 {
-                previous_diff += diffs[0];
-            } else {
-                previous_diff -= diffs[0];
+impl ValueHistory {
+    fn new(inner: Vec<isize>) -> Self {
+        let newton = newton_coefficients(&inner);
+        ValueHistory { inner, newton }
+    }
+
+    // Extrapolates the history to any index, where 0 is the first value. Negative indices
+    // are before the history.
+    fn predict_at(&self, k: i128) -> Result<i128, HistoryError> {
+        // C(k, j) = C(k, j - 1) * (k - j + 1) / j, and the division is always exact. This
+        // works for negative k as well.
+        let mut binomial: i128 = 1;
+        let mut value: i128 = 0;
+        for (j, coefficient) in self
+            .newton
+            .as_ref()
+            .map_err(|e| e.clone())?
+            .iter()
+            .enumerate()
+        {
+            if j > 0 {
+                binomial = binomial
+                    .checked_mul(k - j as i128 + 1)
+                    .ok_or(HistoryError::Overflow)?
+                    / j as i128;
             }
-            iter += 1;
+            value = coefficient
+                .checked_mul(binomial)
+                .and_then(|term| value.checked_add(term))
+                .ok_or(HistoryError::Overflow)?;
         }
-        self.inner[0] - previous_diff
+        Ok(value)
+    }
+
+    fn predict_next(&self) -> Result<i128, HistoryError> {
+        self.predict_at(self.inner.len() as i128)
+    }
+
+    fn predict_previous(&self) -> Result<i128, HistoryError> {
+        self.predict_at(-1)
     }
 }
 
@@ -79,13 +129,269 @@ impl From<Vec<String>> for Report {
     }
 }
 
+// A polynomial in `k` with rational coefficients, written as integer coefficients over a
+// common denominator, from the constant term up.
+#[derive(Debug, PartialEq)]
+struct Polynomial {
+    coefficients: Vec<i128>,
+    denominator: i128,
+}
+
+impl Polynomial {
+    // Converts Newton forward differences to the usual form, knowing that
+    // C(k, j) = k (k - 1) ... (k - j + 1) / j!. Returns None if the coefficients don't fit in
+    // an i128.
+    fn from_newton(newton: &[i128]) -> Option<Polynomial> {
+        let degree = newton.len().saturating_sub(1) as i128;
+        let denominator = (1..=degree).try_fold(1i128, |acc, i| acc.checked_mul(i))?;
+        let mut coefficients = vec![0i128; newton.len().max(1)];
+        // The coefficients of k (k - 1) ... (k - j + 1), and j!.
+        let mut falling = vec![1i128];
+        let mut factorial = 1;
+        for (j, difference) in newton.iter().enumerate() {
+            if j > 0 {
+                let root = j as i128 - 1;
+                let mut next = vec![0; falling.len() + 1];
+                for (i, c) in falling.iter().enumerate() {
+                    next[i + 1] += c;
+                    next[i] = next[i].checked_sub(c.checked_mul(root)?)?;
+                }
+                falling = next;
+                factorial *= j as i128;
+            }
+            let scale = difference.checked_mul(denominator / factorial)?;
+            for (i, c) in falling.iter().enumerate() {
+                coefficients[i] = coefficients[i].checked_add(c.checked_mul(scale)?)?;
+            }
+        }
+        let common = coefficients
+            .iter()
+            .fold(denominator, |acc, c| gcd_i128(acc, *c));
+        Some(Polynomial {
+            coefficients: coefficients.iter().map(|c| c / common).collect(),
+            denominator: denominator / common,
+        })
+    }
+}
+
+// Written from the highest power down, e.g. `k^3/3 - k^2 + 11k/3 + 10`.
+impl Display for Polynomial {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for (power, coefficient) in self.coefficients.iter().enumerate().rev() {
+            if *coefficient == 0 {
+                continue;
+            }
+            let common = gcd_i128(*coefficient, self.denominator);
+            let (numerator, denominator) = (coefficient / common, self.denominator / common);
+            let sign = match (first, numerator < 0) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            let variable = match power {
+                0 => String::new(),
+                1 => "k".to_string(),
+                _ => format!("k^{power}"),
+            };
+            let numerator = match numerator.abs() {
+                1 if power > 0 => String::new(),
+                n => n.to_string(),
+            };
+            write!(f, "{sign}{numerator}{variable}")?;
+            if denominator != 1 {
+                write!(f, "/{denominator}")?;
+            }
+            first = false;
+        }
+        if first {
+            write!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SequenceKind {
+    // All the values are the same.
+    Constant,
+    // The values go up by the same difference each time.
+    Arithmetic { difference: i128 },
+    // A polynomial of degree 2 or more.
+    Polynomial { degree: usize },
+    // The values are multiplied by the same ratio each time, written as a fraction.
+    Geometric { numerator: i128, denominator: i128 },
+    // Anything else, including empty histories.
+    Other,
+}
+
+impl Display for SequenceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceKind::Constant => write!(f, "constant"),
+            SequenceKind::Arithmetic { difference } => {
+                write!(f, "arithmetic with difference {difference}")
+            }
+            SequenceKind::Polynomial { degree } => write!(f, "polynomial of degree {degree}"),
+            SequenceKind::Geometric {
+                numerator,
+                denominator: 1,
+            } => write!(f, "geometric with ratio {numerator}"),
+            SequenceKind::Geometric {
+                numerator,
+                denominator,
+            } => write!(f, "geometric with ratio {numerator}/{denominator}"),
+            SequenceKind::Other => write!(f, "not polynomial"),
+        }
+    }
+}
+
+// What we know about a history, to see where its predictions come from.
+#[derive(Debug)]
+struct HistoryAnalysis {
+    // The rows of the difference table, from the values down to the first row of zeroes, or
+    // to the last row if there is none.
+    pyramid: Vec<Vec<i128>>,
+    // The degree of the polynomial the values follow, which is 0 for a constant.
+    degree: Result<usize, HistoryError>,
+    polynomial: Option<Polynomial>,
+    kind: SequenceKind,
+    next: Result<i128, HistoryError>,
+    previous: Result<i128, HistoryError>,
+}
+
+impl HistoryAnalysis {
+    fn new(history: &ValueHistory) -> Self {
+        let mut pyramid = vec![history
+            .inner
+            .iter()
+            .map(|v| *v as i128)
+            .collect::<Vec<i128>>()];
+        while let Some(row) = pyramid.last().filter(|r| r.len() > 1 && !all_zeroes(r)) {
+            match compute_diffs_array(row) {
+                Some(diffs) => pyramid.push(diffs),
+                None => break,
+            }
+        }
+
+        let degree = history
+            .newton
+            .as_ref()
+            .map(|newton| newton.len().saturating_sub(1))
+            .map_err(|e| e.clone());
+        let polynomial = history
+            .newton
+            .as_ref()
+            .ok()
+            .and_then(|newton| Polynomial::from_newton(newton));
+        let values = &pyramid[0];
+        let kind = match degree {
+            _ if values.is_empty() => SequenceKind::Other,
+            Ok(0) => SequenceKind::Constant,
+            Ok(1) => SequenceKind::Arithmetic {
+                difference: pyramid[1][0],
+            },
+            Ok(degree) => SequenceKind::Polynomial { degree },
+            Err(_) => match geometric_ratio(values) {
+                Some((numerator, denominator)) => SequenceKind::Geometric {
+                    numerator,
+                    denominator,
+                },
+                None => SequenceKind::Other,
+            },
+        };
+        HistoryAnalysis {
+            pyramid,
+            degree,
+            polynomial,
+            kind,
+            next: history.predict_next(),
+            previous: history.predict_previous(),
+        }
+    }
+}
+
+// The ratio between consecutive values, as a reduced fraction with a positive denominator,
+// if it is always the same. At least 3 values are needed to tell.
+fn geometric_ratio(values: &[i128]) -> Option<(i128, i128)> {
+    if values.len() < 3 || values[0] == 0 {
+        return None;
+    }
+    // v[i + 1] / v[i] = v[1] / v[0] for all i.
+    let same_ratio = values.windows(2).all(|w| {
+        let left = w[1].checked_mul(values[0]);
+        left.is_some() && left == w[0].checked_mul(values[1])
+    });
+    if !same_ratio {
+        return None;
+    }
+    let common = gcd_i128(values[1], values[0]) * values[0].signum();
+    Some((values[1] / common, values[0] / common))
+}
+
+// Prints the history and its difference table as in the puzzle, e.g.
+// 10  13  16  21  30  45
+//    3   3   5   9  15
+//      0   2   4   6
+//        2   2   2
+//          0   0
+impl Display for HistoryAnalysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let result = |r: &Result<i128, HistoryError>| match r {
+            Ok(value) => value.to_string(),
+            Err(e) => e.to_string(),
+        };
+        writeln!(f, "kind: {}", self.kind)?;
+        match &self.degree {
+            Ok(degree) => writeln!(f, "degree: {degree}")?,
+            Err(e) => writeln!(f, "degree: {e}")?,
+        }
+        if let Some(polynomial) = &self.polynomial {
+            writeln!(f, "closed form: {polynomial}")?;
+        }
+        writeln!(f, "next: {}", result(&self.next))?;
+        writeln!(f, "previous: {}", result(&self.previous))?;
+        // Every number is right-aligned to the same even width and followed by a gap of two
+        // spaces, so that each row can be shifted by half of a column.
+        let width = self
+            .pyramid
+            .iter()
+            .flatten()
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .div_ceil(2)
+            * 2;
+        for (depth, row) in self.pyramid.iter().enumerate() {
+            let row = row
+                .iter()
+                .map(|v| format!("{v:>width$}"))
+                .collect::<Vec<String>>()
+                .join("  ");
+            writeln!(f, "{}{row}", " ".repeat(depth * (width + 2) / 2))?;
+        }
+        Ok(())
+    }
+}
+
 impl Report {
-    fn predict_next_sum(&self) -> isize {
-        self.inner.iter().map(|v| v.predict_next()).sum()
+    fn analyze(&self) -> Vec<HistoryAnalysis> {
+        self.inner.iter().map(HistoryAnalysis::new).collect()
     }
 
-    fn predict_previous_sum(&self) -> isize {
-        self.inner.iter().map(|v| v.predict_previous()).sum()
+    fn predict_next_sum(&self) -> Result<i128, HistoryError> {
+        self.inner.iter().try_fold(0i128, |sum, v| {
+            sum.checked_add(v.predict_next()?)
+                .ok_or(HistoryError::Overflow)
+        })
+    }
+
+    fn predict_previous_sum(&self) -> Result<i128, HistoryError> {
+        self.inner.iter().try_fold(0i128, |sum, v| {
+            sum.checked_add(v.predict_previous()?)
+                .ok_or(HistoryError::Overflow)
+        })
     }
 }
 
@@ -93,13 +399,26 @@ fn main() {
     let path = PathBuf::from("src/day9/input.txt");
     let input = read_lines(path);
     let report = Report::from(input);
-    println!("Part 1: {}", report.predict_next_sum());
-    println!("Part 2: {}", report.predict_previous_sum());
+    match report.predict_next_sum() {
+        Ok(sum) => println!("Part 1: {sum}"),
+        Err(e) => println!("Part 1: {e}"),
+    }
+    match report.predict_previous_sum() {
+        Ok(sum) => println!("Part 2: {sum}"),
+        Err(e) => println!("Part 2: {e}"),
+    }
+
+    // Pass `--analyze` to see how each history is extrapolated.
+    if env::args().any(|arg| arg == "--analyze") {
+        for (index, analysis) in report.analyze().iter().enumerate() {
+            print!("\nHistory {}\n{analysis}", index + 1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Report, ValueHistory};
+    use crate::{HistoryAnalysis, HistoryError, Polynomial, Report, SequenceKind, ValueHistory};
 
     #[test]
     fn test_part1() {
@@ -111,25 +430,25 @@ mod tests {
             .collect();
 
         let history = vec![0, 3, 6, 9, 12, 15];
-        let history = ValueHistory { inner: history };
-        assert_eq!(history.predict_next(), 18);
+        let history = ValueHistory::new(history);
+        assert_eq!(history.predict_next(), Ok(18));
         let report = Report::from(input);
-        assert_eq!(report.predict_next_sum(), 114);
+        assert_eq!(report.predict_next_sum(), Ok(114));
     }
 
     #[test]
     fn test_predict_prev() {
         let input = "10 13 16 21 30 45".to_string();
         let history = ValueHistory::from(&input);
-        assert_eq!(history.predict_previous(), 5);
+        assert_eq!(history.predict_previous(), Ok(5));
 
         let input = "0 3 6 9 12 15".to_string();
         let history = ValueHistory::from(&input);
-        assert_eq!(history.predict_previous(), -3);
+        assert_eq!(history.predict_previous(), Ok(-3));
 
         let input = "1 3 6 10 15 21".to_string();
         let history = ValueHistory::from(&input);
-        assert_eq!(history.predict_previous(), 0);
+        assert_eq!(history.predict_previous(), Ok(0));
     }
 
     #[test]
@@ -142,6 +461,128 @@ mod tests {
             .collect();
 
         let report = Report::from(input);
-        assert_eq!(report.predict_previous_sum(), 2);
+        assert_eq!(report.predict_previous_sum(), Ok(2));
+    }
+
+    #[test]
+    fn test_predict_at() {
+        let input = "10 13 16 21 30 45".to_string();
+        let history = ValueHistory::from(&input);
+        assert_eq!(history.newton, Ok(vec![10, 3, 0, 2]));
+        assert_eq!(history.predict_at(0), Ok(10));
+        assert_eq!(history.predict_at(5), Ok(45));
+        assert_eq!(history.predict_at(6), Ok(68));
+        assert_eq!(history.predict_at(7), Ok(101));
+        assert_eq!(history.predict_at(-1), Ok(5));
+        assert_eq!(history.predict_at(-2), Ok(-4));
+
+        // The predictions don't fit in an isize.
+        let history = ValueHistory::new(vec![isize::MIN + 1, 0, isize::MAX]);
+        let max = isize::MAX as i128;
+        assert_eq!(history.newton, Ok(vec![-max, max]));
+        assert_eq!(history.predict_next(), Ok(2 * max));
+        assert_eq!(history.predict_at(-1), Ok(-2 * max));
+        assert_eq!(history.predict_at(1 << 100), Err(HistoryError::Overflow));
+    }
+
+    #[test]
+    fn test_degenerate_histories() {
+        let history = ValueHistory::new(vec![]);
+        assert_eq!(history.predict_next(), Err(HistoryError::Empty));
+        assert_eq!(history.predict_previous(), Err(HistoryError::Empty));
+
+        // A single value is not enough to tell that the sequence is constant, unless it is
+        // already a row of zeroes.
+        let history = ValueHistory::new(vec![7]);
+        assert_eq!(history.predict_next(), Err(HistoryError::NotPolynomial(1)));
+        assert_eq!(
+            history.predict_previous(),
+            Err(HistoryError::NotPolynomial(1))
+        );
+        let history = ValueHistory::new(vec![0]);
+        assert_eq!(history.predict_next(), Ok(0));
+
+        // 1 2 4 8 has differences 1 2 4, then 1 2 and then 1, never all zeroes.
+        let history = ValueHistory::new(vec![1, 2, 4, 8]);
+        assert_eq!(history.predict_next(), Err(HistoryError::NotPolynomial(4)));
+        assert_eq!(
+            history.predict_previous().unwrap_err().to_string(),
+            "the sequence is not polynomial within its length of 4 values"
+        );
+        // Two values are not enough to tell that the sequence is linear, three are.
+        let history = ValueHistory::new(vec![3, 5]);
+        assert_eq!(history.predict_next(), Err(HistoryError::NotPolynomial(2)));
+        let history = ValueHistory::new(vec![3, 5, 7]);
+        assert_eq!(history.predict_next(), Ok(9));
+        let history = ValueHistory::new(vec![3, 3]);
+        assert_eq!(history.predict_next(), Ok(3));
+
+        // The differences of alternating extremes overflow.
+        let history = ValueHistory::new([isize::MIN, isize::MAX].repeat(40));
+        assert_eq!(history.predict_next(), Err(HistoryError::Overflow));
+
+        // An empty line in a report is an empty history.
+        let report = Report::from(vec!["0 3 6".to_string(), String::new()]);
+        assert_eq!(report.predict_next_sum(), Err(HistoryError::Empty));
+    }
+
+    #[test]
+    fn test_analysis() {
+        let analyze = |values: Vec<isize>| HistoryAnalysis::new(&ValueHistory::new(values));
+
+        let analysis = analyze(vec![10, 13, 16, 21, 30, 45]);
+        assert_eq!(analysis.degree, Ok(3));
+        assert_eq!(analysis.kind, SequenceKind::Polynomial { degree: 3 });
+        assert_eq!(
+            analysis.polynomial,
+            Some(Polynomial {
+                coefficients: vec![30, 11, -3, 1],
+                denominator: 3,
+            })
+        );
+        assert_eq!(
+            analysis.to_string(),
+            "kind: polynomial of degree 3
+degree: 3
+closed form: k^3/3 - k^2 + 11k/3 + 10
+next: 68
+previous: 5
+10  13  16  21  30  45
+   3   3   5   9  15
+     0   2   4   6
+       2   2   2
+         0   0
+"
+        );
+
+        let analysis = analyze(vec![1, 3, 6, 10, 15, 21]);
+        assert_eq!(analysis.polynomial.unwrap().to_string(), "k^2/2 + 3k/2 + 1");
+        let analysis = analyze(vec![0, 3, 6, 9, 12, 15]);
+        assert_eq!(analysis.kind, SequenceKind::Arithmetic { difference: 3 });
+        assert_eq!(analysis.polynomial.unwrap().to_string(), "3k");
+        assert_eq!(analyze(vec![4, 4, 4]).kind, SequenceKind::Constant);
+        let analysis = analyze(vec![-7]);
+        assert_eq!(analysis.degree, Err(HistoryError::NotPolynomial(1)));
+        assert_eq!(analysis.kind, SequenceKind::Other);
+        assert_eq!(analyze(vec![-7, -7]).polynomial.unwrap().to_string(), "-7");
+        assert_eq!(analyze(vec![0, 0]).polynomial.unwrap().to_string(), "0");
+
+        let analysis = analyze(vec![3, 6, 12, 24, 48]);
+        assert_eq!(
+            analysis.kind,
+            SequenceKind::Geometric {
+                numerator: 2,
+                denominator: 1,
+            }
+        );
+        assert_eq!(analysis.degree, Err(HistoryError::NotPolynomial(5)));
+        assert_eq!(analysis.polynomial, None);
+        assert_eq!(analysis.pyramid.len(), 5);
+        assert_eq!(
+            analyze(vec![-8, 12, -18]).kind.to_string(),
+            "geometric with ratio -3/2"
+        );
+        assert_eq!(analyze(vec![1, 2, 5, 20]).kind, SequenceKind::Other);
+        assert_eq!(analyze(vec![]).kind, SequenceKind::Other);
     }
 }
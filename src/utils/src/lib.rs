@@ -1,4 +1,6 @@
-use std::fmt::Debug;
+pub mod num;
+
+use std::fmt::{Debug, Display, Formatter};
 use std::fs::read_to_string;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -11,6 +13,20 @@ pub fn read_lines(path: PathBuf) -> Vec<String> {
         .collect::<Vec<String>>()
 }
 
+// An error in the input, with what went wrong described by `kind`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<K: Display> {
+    // The number of the line with the error, starting at 1.
+    pub line: usize,
+    pub kind: K,
+}
+
+impl<K: Display> Display for ParseError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
 // Create a vector from numbers separated by one or more ' '. T must be a numeric type.
 pub fn as_vec<T: FromStr>(input: &str) -> Vec<T> where <T as FromStr>::Err: Debug {
     input.split(' ').filter(|s| !s.is_empty()).map(|v|v.parse::<T>().unwrap()).collect::<Vec<T>>()
@@ -0,0 +1,191 @@
+use std::fmt::{Display, Formatter};
+
+// Highest common factor with the Euclidean algorithm. gcd(0, 0) is 0.
+pub fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// Highest common factor of two signed numbers, which is never negative. Panics if it is
+// 2^127, i.e. for gcd(i128::MIN, i128::MIN) and gcd(i128::MIN, 0).
+pub fn gcd_i128(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    i128::try_from(a).unwrap()
+}
+
+// Highest common factor with Stein's algorithm, which only uses shifts and subtractions.
+pub fn binary_gcd(mut a: usize, mut b: usize) -> usize {
+    if a == 0 || b == 0 {
+        return a | b;
+    }
+    // The power of 2 that divides both numbers.
+    let shift = (a | b).trailing_zeros();
+    a >>= a.trailing_zeros();
+    loop {
+        b >>= b.trailing_zeros();
+        if a > b {
+            (a, b) = (b, a);
+        }
+        b -= a;
+        if b == 0 {
+            return a << shift;
+        }
+    }
+}
+
+// Least common multiple, or None if it doesn't fit in a usize. lcm(0, x) is 0.
+pub fn lcm(a: usize, b: usize) -> Option<usize> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    // Dividing first keeps the intermediate value as small as the result.
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+// Least common multiple of all the values, which is 1 for no values.
+pub fn lcm_of<I: IntoIterator<Item = usize>>(values: I) -> Option<usize> {
+    values.into_iter().try_fold(1, lcm)
+}
+
+// Returns (g, x, y) such that a * x + b * y = g, where g is the highest common factor of a
+// and b.
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+    if old_r < 0 {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+// Returns x such that a * x % m == 1, if a and m are coprime.
+pub fn mod_inverse(a: usize, m: usize) -> Option<usize> {
+    if m == 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(a as i128, m as i128);
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(m as i128) as usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrtError {
+    // The congruences contradict each other, e.g. x % 2 == 0 and x % 4 == 1.
+    NoSolution,
+    // The least common multiple of the moduli doesn't fit in a usize.
+    Overflow,
+    ZeroModulus,
+}
+
+impl Display for CrtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrtError::NoSolution => write!(f, "the congruences have no common solution"),
+            CrtError::Overflow => write!(f, "the solution doesn't fit in a usize"),
+            CrtError::ZeroModulus => write!(f, "a modulus is 0"),
+        }
+    }
+}
+
+// Chinese remainder theorem: finds the smallest x such that x % modulus == remainder for
+// all the (remainder, modulus) pairs. The moduli don't need to be coprime. The solutions
+// are then x plus any multiple of the least common multiple of the moduli, which is
+// returned as well.
+pub fn crt<I: IntoIterator<Item = (usize, usize)>>(
+    congruences: I,
+) -> Result<(usize, usize), CrtError> {
+    congruences.into_iter().try_fold((0, 1), |(a, m), (b, n)| {
+        if n == 0 {
+            return Err(CrtError::ZeroModulus);
+        }
+        let b = b % n;
+        let g = gcd(m, n);
+        if a % g != b % g {
+            return Err(CrtError::NoSolution);
+        }
+        let lcm = lcm(m, n).ok_or(CrtError::Overflow)?;
+        // x = a + m * k, where m * k = b - a (mod n), so k = (b - a) / g * inverse of
+        // m / g (mod n / g).
+        let n_g = (n / g) as u128;
+        let diff = (b as i128 - a as i128).rem_euclid(n as i128) as u128 / g as u128;
+        let inverse = mod_inverse(m / g, n / g).unwrap_or(0) as u128;
+        let k = diff % n_g * inverse % n_g;
+        let x = (a as u128 + k * m as u128) % lcm as u128;
+        Ok((x as usize, lcm))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gcd() {
+        for (a, b, expected) in [(0, 0, 0), (0, 7, 7), (12, 18, 6), (17, 5, 1), (48, 180, 12)] {
+            assert_eq!(gcd(a, b), expected);
+            assert_eq!(binary_gcd(a, b), expected);
+            assert_eq!(binary_gcd(b, a), expected);
+        }
+        assert_eq!(binary_gcd(usize::MAX, usize::MAX - 1), 1);
+        assert_eq!(gcd_i128(-12, 18), 6);
+        assert_eq!(gcd_i128(12, -18), 6);
+        assert_eq!(gcd_i128(0, -7), 7);
+        assert_eq!(gcd_i128(0, 0), 0);
+        assert_eq!(gcd_i128(i128::MIN, 6), 2);
+    }
+
+    #[test]
+    fn test_lcm() {
+        assert_eq!(lcm(5, 15), Some(15));
+        assert_eq!(lcm(1, 2), Some(2));
+        assert_eq!(lcm(0, 2), Some(0));
+        assert_eq!(lcm(usize::MAX, usize::MAX), Some(usize::MAX));
+        assert_eq!(lcm(usize::MAX, 2), None);
+        assert_eq!(lcm_of([2, 3, 4]), Some(12));
+        assert_eq!(lcm_of([]), Some(1));
+        assert_eq!(lcm_of([1 << 40, 3 << 30, 5 << 20, 7]), Some(105 << 40));
+        assert_eq!(lcm_of([1 << 40, 3, 5, 7, 11, 13, 17, 19, 23, 29]), None);
+    }
+
+    #[test]
+    fn test_extended_gcd() {
+        assert_eq!(extended_gcd(240, 46), (2, -9, 47));
+        let (g, x, y) = extended_gcd(-12, 18);
+        assert_eq!(g, 6);
+        assert_eq!(-12 * x + 18 * y, 6);
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(10, 17), Some(12));
+        assert_eq!(mod_inverse(2, 4), None);
+        assert_eq!(mod_inverse(5, 1), Some(0));
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt([(2, 3), (3, 5), (2, 7)]), Ok((23, 105)));
+        assert_eq!(crt([(2, 4), (4, 6)]), Ok((10, 12)));
+        assert_eq!(crt([(2, 4), (3, 6)]), Err(CrtError::NoSolution));
+        assert_eq!(crt([(1, 0)]), Err(CrtError::ZeroModulus));
+        assert_eq!(crt([]), Ok((0, 1)));
+        assert_eq!(
+            crt([(1, usize::MAX), (0, usize::MAX - 1)]),
+            Err(CrtError::Overflow)
+        );
+        let big = (1 << 61) - 1;
+        assert_eq!(crt([(5, big), (7, 1 << 2)]), Ok((2 * big + 5, big << 2)));
+    }
+}